@@ -1,18 +1,29 @@
 use ascii_table::{AsciiTable, Column};
+use chrono::{SecondsFormat, Utc};
+use dialoguer::console::StyledObject;
 use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
 use serde_ini;
-use serde_json::json;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::fmt::Display;
-use std::fs::{write, OpenOptions};
+use std::fs::{read_to_string, write, OpenOptions};
 use std::io::BufReader;
 use std::process::exit;
+use std::time::Duration;
+use tokio::time::sleep;
 
+use crate::api::{engine, job};
+use crate::config::Limits;
 use crate::dialoguer as dlg;
-use crate::http::{get_v1, post_v1};
+use crate::dump::{map_values, Dump, TableKeys};
+use crate::http::{get_env, get_v1, post_v1, HttpError};
+use crate::policy::PolicyCheck;
+use crate::state::State;
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct AWSCLICredentials {
@@ -33,15 +44,227 @@ pub struct NewDbResponse {
 pub struct PlanMeta {
   columns: Vec<String>,
   records: Vec<Vec<String>>,
+  // Cloud values of each record to update or replace, as read by the engine and in the
+  // same order as `records`. An empty record means the prior values are unknown. Plans
+  // without them show the records to change as a table instead of field changes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  previousRecords: Option<Vec<Vec<String>>>,
 }
 #[derive(Deserialize, Debug, Clone, Serialize)]
 #[allow(non_snake_case)]
 pub struct PlanResponse {
   iasqlPlanVersion: i32,
-  toCreate: HashMap<String, PlanMeta>,
-  toUpdate: HashMap<String, PlanMeta>,
-  toReplace: HashMap<String, PlanMeta>,
-  toDelete: HashMap<String, PlanMeta>,
+  toCreate: BTreeMap<String, PlanMeta>,
+  toUpdate: BTreeMap<String, PlanMeta>,
+  toReplace: BTreeMap<String, PlanMeta>,
+  toDelete: BTreeMap<String, PlanMeta>,
+}
+
+// Plan saved with `plan --out` to be checked against the changes `apply --plan` would make
+#[derive(Deserialize, Debug, Clone, Serialize)]
+#[allow(non_snake_case)]
+pub struct SavedPlan {
+  dbAlias: String,
+  createdAt: String,
+  hash: String,
+  // Modules and tables the plan was scoped to, which `apply --plan` must target too
+  #[serde(default)]
+  modules: Vec<String>,
+  #[serde(default)]
+  tables: Vec<String>,
+  plan: PlanResponse,
+}
+
+impl PlanResponse {
+  // Content hash that does not depend on the order of tables or records in the response
+  pub fn hash(&self) -> String {
+    let mut plan = self.clone();
+    for segment in [
+      &mut plan.toCreate,
+      &mut plan.toUpdate,
+      &mut plan.toReplace,
+      &mut plan.toDelete,
+    ] {
+      for meta in segment.values_mut() {
+        meta.records.sort();
+      }
+    }
+    // serde_json maps are sorted by key, which makes the serialization deterministic
+    let canonical = serde_json::to_value(&plan).unwrap().to_string();
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+  }
+
+  pub fn segment(&self, action: PlanAction) -> &BTreeMap<String, PlanMeta> {
+    match action {
+      PlanAction::Create => &self.toCreate,
+      PlanAction::Update => &self.toUpdate,
+      PlanAction::Replace => &self.toReplace,
+      PlanAction::Delete => &self.toDelete,
+    }
+  }
+
+  fn segment_mut(&mut self, action: PlanAction) -> &mut BTreeMap<String, PlanMeta> {
+    match action {
+      PlanAction::Create => &mut self.toCreate,
+      PlanAction::Update => &mut self.toUpdate,
+      PlanAction::Replace => &mut self.toReplace,
+      PlanAction::Delete => &mut self.toDelete,
+    }
+  }
+
+  // Copy of the plan with only the changes selected by the filter
+  fn filtered(&self, filter: &PlanFilter, modules: &BTreeMap<String, String>) -> PlanResponse {
+    let mut filtered = self.clone();
+    for action in PlanAction::all().iter() {
+      filtered
+        .segment_mut(*action)
+        .retain(|table, _| filter.shows(*action, table, modules.get(table)));
+    }
+    filtered
+  }
+
+  fn total(&self) -> usize {
+    PlanAction::all().iter().map(|a| self.count(*a)).sum()
+  }
+
+  // Number of records affected by an action across all tables
+  pub fn count(&self, action: PlanAction) -> usize {
+    self
+      .segment(action)
+      .values()
+      .map(|meta| meta.records.len())
+      .sum()
+  }
+
+  fn sort_records(&mut self) {
+    for segment in [
+      &mut self.toCreate,
+      &mut self.toUpdate,
+      &mut self.toReplace,
+      &mut self.toDelete,
+    ] {
+      for meta in segment.values_mut() {
+        meta.sort_records();
+      }
+    }
+  }
+
+  // Whether records to update or replace come without their prior values
+  fn lacks_previous_records(&self) -> bool {
+    [&self.toUpdate, &self.toReplace]
+      .iter()
+      .flat_map(|segment| segment.values())
+      .any(|meta| !meta.records.is_empty() && meta.previousRecords.is_none())
+  }
+
+  pub fn has_changes(&self) -> bool {
+    PlanAction::all()
+      .iter()
+      .any(|action| self.count(*action) > 0)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlanOutput {
+  Text,
+  Json,
+  Markdown,
+  Html,
+}
+
+impl PlanOutput {
+  pub fn parse(output: &str) -> Option<PlanOutput> {
+    match output {
+      "text" => Some(PlanOutput::Text),
+      "json" => Some(PlanOutput::Json),
+      "markdown" => Some(PlanOutput::Markdown),
+      "html" => Some(PlanOutput::Html),
+      _ => None,
+    }
+  }
+}
+
+// Restricts the changes displayed by `plan`, `apply` and `sync`. Plans saved to a file or
+// printed as JSON are never filtered. The modules and tables are also the targets of
+// `apply` and `sync`, which then leave the rest of the db untouched.
+#[derive(Debug, Clone, Default)]
+pub struct PlanFilter {
+  pub actions: Vec<PlanAction>,
+  pub tables: Vec<String>,
+  pub modules: Vec<String>,
+  // Only display the number of changes per table
+  pub summary: bool,
+}
+
+// Capability of engines able to apply or sync only some modules or tables
+const SCOPE_CAPABILITY: &str = "scope";
+
+impl PlanFilter {
+  // `apply` and `sync` only make the changes of the modules and tables selected
+  fn is_targeted(&self) -> bool {
+    !self.modules.is_empty() || !self.tables.is_empty()
+  }
+
+  // Modules can be given with or without their version
+  fn shows(&self, action: PlanAction, table: &str, module: Option<&String>) -> bool {
+    let module_matches = |m: &String| {
+      module
+        .map(|module| module == m || module.split('@').next() == Some(m.as_str()))
+        .unwrap_or(false)
+    };
+    (self.actions.is_empty() || self.actions.contains(&action))
+      && (self.tables.is_empty() || self.tables.iter().any(|t| t == table))
+      && (self.modules.is_empty() || self.modules.iter().any(module_matches))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlanAction {
+  Create,
+  Update,
+  Replace,
+  Delete,
+}
+
+impl PlanAction {
+  pub fn parse(action: &str) -> Option<PlanAction> {
+    PlanAction::all()
+      .iter()
+      .copied()
+      .find(|a| a.verb() == action)
+  }
+
+  pub fn all() -> [PlanAction; 4] {
+    [
+      PlanAction::Create,
+      PlanAction::Update,
+      PlanAction::Replace,
+      PlanAction::Delete,
+    ]
+  }
+
+  pub fn verb(&self) -> &'static str {
+    match self {
+      PlanAction::Create => "create",
+      PlanAction::Update => "update",
+      PlanAction::Replace => "replace",
+      PlanAction::Delete => "delete",
+    }
+  }
+
+  // Renders text with the color associated to the action
+  pub fn styled(&self, el: &str) -> StyledObject<String> {
+    match self {
+      PlanAction::Create => dlg::green(el),
+      PlanAction::Update => dlg::yellow(el),
+      PlanAction::Replace => dlg::magenta(el),
+      PlanAction::Delete => dlg::red(el),
+    }
+  }
+
+  pub fn label(&self) -> StyledObject<String> {
+    self.styled(self.verb())
+  }
 }
 
 // TODO load regions at startup based on aws services and schema since not all regions support all services.
@@ -84,26 +307,49 @@ fn get_aws_cli_creds() -> Result<HashMap<String, AWSCLICredentials>, String> {
 }
 
 fn get_server() -> &'static str {
-  let default = if cfg!(debug_assertions) {
-    "local"
-  } else {
-    "production"
-  };
-  let env = std::env::var("IASQL_ENV").unwrap_or(default.to_string());
-  match env.as_str() {
+  match get_env().as_str() {
     "local" => "127.0.0.1:5432",
     _ => "db.iasql.com",
   }
 }
 
+// The db given, otherwise the one in IASQL_DB or set with `iasql use`, otherwise the one
+// picked from the hosted dbs
 pub async fn get_or_select_db(db_opt: Option<&str>) -> String {
   let dbs = get_dbs(true).await;
+  let default_db = match std::env::var("IASQL_DB") {
+    Ok(db) if !db.is_empty() => Some((db, "IASQL_DB")),
+    _ => State::load().current_db().map(|db| (db, "iasql use")),
+  };
+  if let (None, Some((db, source))) = (db_opt, &default_db) {
+    if !dbs.contains(db) {
+      eprintln!(
+        "{} {} {} {} {} set with {}",
+        dlg::err_prefix(),
+        dlg::bold("Nonexistent hosted db"),
+        dlg::divider(),
+        dlg::red(db),
+        dlg::divider(),
+        source,
+      );
+      exit(1);
+    }
+    eprintln!(
+      "{} {} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold("IaSQL db"),
+      dlg::divider(),
+      dlg::green(db),
+      dlg::gray(&format!("(set with {})", source)),
+    );
+    return db.clone();
+  }
   if db_opt.is_none() {
     let selection = if dbs.len() > 1 {
       dlg::select_with_default("Pick hosted IaSQL db", &dbs, 0)
     } else {
       // if only one db, skip selection
-      println!(
+      eprintln!(
         "{} {} {} {}",
         dlg::success_prefix(),
         dlg::bold("IaSQL db"),
@@ -130,7 +376,55 @@ pub async fn get_or_select_db(db_opt: Option<&str>) -> String {
   }
 }
 
-async fn get_or_input_db(db_opt: Option<&str>) -> String {
+// Sets the db commands default to for the current environment
+pub async fn use_db(db_opt: Option<&str>, unset: bool) {
+  let mut state = State::load();
+  if unset {
+    state.set_current_db(None);
+    state.save();
+    println!(
+      "{} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold("Unset current db for"),
+      dlg::divider(),
+      dlg::green(&get_env()),
+    );
+    return;
+  }
+  let dbs = get_dbs(true).await;
+  let db = match db_opt {
+    Some(db) => db.to_string(),
+    None => {
+      let current = state
+        .current_db()
+        .and_then(|c| dbs.iter().position(|db| *db == c))
+        .unwrap_or(0);
+      dbs[dlg::select_with_default("Pick hosted IaSQL db", &dbs, current)].clone()
+    }
+  };
+  if !dbs.contains(&db) {
+    eprintln!(
+      "{} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Nonexistent hosted db"),
+      dlg::divider(),
+      dlg::red(&db)
+    );
+    exit(1);
+  }
+  state.set_current_db(Some(&db));
+  state.save();
+  println!(
+    "{} {} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold("Using db"),
+    dlg::divider(),
+    dlg::green(&db),
+    dlg::gray(&format!("({})", get_env())),
+  );
+}
+
+pub async fn get_or_input_db(db_opt: Option<&str>) -> String {
   let db = if db_opt.is_none() {
     dlg::optional_input("Optional db name")
   } else {
@@ -158,7 +452,7 @@ pub fn get_or_input_arg(arg_opt: Option<&str>, in_title: &str) -> String {
   }
 }
 
-async fn get_dbs(exit_if_none: bool) -> Vec<String> {
+pub async fn get_dbs(exit_if_none: bool) -> Vec<String> {
   let resp = get_v1("db/list").await;
   let res = match &resp {
     Ok(r) => r,
@@ -185,40 +479,14 @@ async fn get_dbs(exit_if_none: bool) -> Vec<String> {
   dbs
 }
 
-pub async fn export(db: &str, dump_file: String, data_only: bool) {
-  let sp = ProgressBar::new_spinner();
-  sp.enable_steady_tick(10);
-  sp.set_message("Export in progress");
-  let df = if !dump_file.ends_with(".sql") {
-    format!("{}.sql", dump_file)
-  } else {
-    dump_file
-  };
+pub async fn export_dump(db: &str, data_only: bool) -> String {
   let body = json!({
     "dbAlias": db,
     "dataOnly": data_only,
   });
-  let resp = post_v1("db/export/", body).await;
-  sp.finish_and_clear();
+  let resp = job::run("db/export/", body, "Export in progress").await;
   match &resp {
-    Ok(r) => {
-      let export_response: String = serde_json::from_str(r).unwrap();
-      match write(df, export_response) {
-        Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
-        Err(e) => {
-          eprintln!(
-            "{} {} {} {} {} {}",
-            dlg::err_prefix(),
-            dlg::bold("Failed to export hosted db"),
-            dlg::divider(),
-            dlg::red(db),
-            dlg::divider(),
-            e
-          );
-          exit(1);
-        }
-      };
-    }
+    Ok(r) => serde_json::from_str(r).unwrap(),
     Err(e) => {
       eprintln!(
         "{} {} {} {} {} {}",
@@ -231,9 +499,40 @@ pub async fn export(db: &str, dump_file: String, data_only: bool) {
       );
       exit(1);
     }
+  }
+}
+
+pub async fn export(db: &str, dump_file: String, data_only: bool) {
+  let df = if !dump_file.ends_with(".sql") {
+    format!("{}.sql", dump_file)
+  } else {
+    dump_file
+  };
+  let export_response = export_dump(db, data_only).await;
+  match write(df, export_response) {
+    Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to export hosted db"),
+        dlg::divider(),
+        dlg::red(db),
+        dlg::divider(),
+        e
+      );
+      exit(1);
+    }
   };
 }
 
+pub async fn run_sql(db: &str, sql: &str) -> Result<String, HttpError> {
+  let body = json!({
+    "sql": sql,
+  });
+  post_v1(&format!("db/run/{}", db), body).await
+}
+
 pub async fn list() {
   let dbs = get_dbs(true).await;
   let mut table = AsciiTable::default();
@@ -257,114 +556,1085 @@ pub async fn remove(db: &str, noninteractive: bool) {
     let removal = dlg::confirm_with_default("Press enter to confirm removal", true);
     if !removal {
       println!(
-        "{} {} {} {}",
+        "{} {} {} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Did not remove db"),
+        dlg::divider(),
+        dlg::yellow(db)
+      );
+      exit(0);
+    }
+  }
+  let resp = get_v1(&format!("db/remove/{}", db)).await;
+  match &resp {
+    Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to remove db"),
+        dlg::divider(),
+        dlg::red(db),
+        dlg::divider(),
+        e.message
+      );
+      exit(1);
+    }
+  };
+}
+
+// Longest value displayed in a cell before it is truncated
+const MAX_CELL_WIDTH: usize = 48;
+
+fn truncate_cell(value: &str) -> String {
+  let value = value.replace('\n', "\\n");
+  if value.chars().count() <= MAX_CELL_WIDTH {
+    value
+  } else {
+    let truncated: String = value.chars().take(MAX_CELL_WIDTH - 1).collect();
+    format!("{}…", truncated)
+  }
+}
+
+// Compares record keys numerically when both are numbers
+fn compare_keys(a: &str, b: &str) -> Ordering {
+  match (a.parse::<i64>(), b.parse::<i64>()) {
+    (Ok(a), Ok(b)) => a.cmp(&b),
+    _ => a.cmp(b),
+  }
+}
+
+impl PlanMeta {
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  pub fn records(&self) -> &[Vec<String>] {
+    &self.records
+  }
+
+  // Position of the column identifying each record
+  pub fn key_index(&self) -> usize {
+    self.columns.iter().position(|c| c == "id").unwrap_or(0)
+  }
+
+  // Sorts the records by their key keeping their prior values, if any, aligned
+  fn sort_records(&mut self) {
+    let key_index = self.key_index();
+    let mut previous = self.previousRecords.take().map(|p| p.into_iter());
+    let mut records: Vec<(Vec<String>, Option<Vec<String>>)> = self
+      .records
+      .drain(..)
+      .map(|r| (r, previous.as_mut().and_then(|p| p.next())))
+      .collect();
+    records.sort_by(|(a, _), (b, _)| {
+      let a_key = a.get(key_index).map(|k| k.as_str()).unwrap_or("");
+      let b_key = b.get(key_index).map(|k| k.as_str()).unwrap_or("");
+      compare_keys(a_key, b_key).then(a.cmp(b))
+    });
+    let has_previous = previous.is_some();
+    let (records, previous): (Vec<Vec<String>>, Vec<Option<Vec<String>>>) =
+      records.into_iter().unzip();
+    self.records = records;
+    if has_previous {
+      self.previousRecords = Some(
+        previous
+          .into_iter()
+          .map(|p| p.unwrap_or_default())
+          .collect(),
+      );
+    }
+  }
+}
+
+// Rows returned by `run_sql`, either wrapped in the result of each statement or as is
+fn query_rows(resp: &str) -> Vec<serde_json::Map<String, Value>> {
+  let value: Value = serde_json::from_str(resp).unwrap_or(Value::Null);
+  let rows = match value {
+    Value::Array(results) if results.iter().any(|r| r.get("queryRes").is_some()) => results
+      .into_iter()
+      .filter_map(|r| match r.get("queryRes") {
+        Some(Value::Array(rows)) => Some(rows.clone()),
+        _ => None,
+      })
+      .flatten()
+      .collect(),
+    Value::Array(rows) => rows,
+    _ => vec![],
+  };
+  rows
+    .into_iter()
+    .filter_map(|r| match r {
+      Value::Object(row) => Some(row),
+      _ => None,
+    })
+    .collect()
+}
+
+// Renders the fields that change in each record, collapsing the unchanged ones
+fn field_change_lines(
+  meta: &PlanMeta,
+  previous: &[Vec<String>],
+  action: PlanAction,
+) -> Vec<String> {
+  let key_index = meta.key_index();
+  let mut lines = vec![];
+  for (i, record) in meta.records.iter().enumerate() {
+    let key = format!(
+      "{}={}",
+      meta.columns[key_index],
+      record.get(key_index).cloned().unwrap_or_default()
+    );
+    lines.push(format!(
+      "  {} {}",
+      action.styled("~"),
+      dlg::bold(&truncate_cell(&key))
+    ));
+    let before = match previous.get(i) {
+      Some(before) if !before.is_empty() => before,
+      _ => {
+        lines.push(format!("      {}", dlg::gray("prior values unknown")));
+        for (column, value) in meta.columns.iter().zip(record.iter()) {
+          lines.push(format!(
+            "      {}: {}",
+            column,
+            action.styled(&truncate_cell(value))
+          ));
+        }
+        continue;
+      }
+    };
+    let mut unchanged = 0;
+    for (j, (column, value)) in meta.columns.iter().zip(record.iter()).enumerate() {
+      let old = before.get(j).cloned().unwrap_or_default();
+      if &old == value {
+        unchanged += 1;
+        continue;
+      }
+      lines.push(format!(
+        "      {}: {} -> {}",
+        column,
+        PlanAction::Delete.styled(&truncate_cell(&old)),
+        PlanAction::Create.styled(&truncate_cell(value)),
+      ));
+    }
+    if unchanged == meta.columns.len() {
+      lines.push(format!("      {}", dlg::gray("no field changes detected")));
+    } else if unchanged > 0 {
+      let field_text = if unchanged == 1 { "field" } else { "fields" };
+      lines.push(format!(
+        "      {}",
+        dlg::gray(&format!("{} unchanged {} hidden", unchanged, field_text))
+      ));
+    }
+  }
+  lines
+}
+
+fn emit_plan_segment(table_name: &str, meta: &PlanMeta, action: PlanAction) {
+  let count = meta.records.len();
+  let record_text = if count == 1 { "record" } else { "records" };
+  println!(
+    "{} has {} {} to {}",
+    dlg::bold(table_name),
+    dlg::bold(&format!("{}", count)),
+    dlg::bold(record_text),
+    action.label(),
+  );
+  if let Some(previous) = &meta.previousRecords {
+    for line in field_change_lines(meta, previous, action) {
+      println!("{}", line);
+    }
+    return;
+  }
+  let mut table = AsciiTable {
+    max_width: 160,
+    ..AsciiTable::default()
+  };
+  for (i, column) in meta.columns.iter().enumerate() {
+    table.columns.insert(
+      i,
+      Column {
+        header: column.to_string(),
+        ..Column::default()
+      },
+    );
+  }
+  let records: Vec<Vec<String>> = meta
+    .records
+    .iter()
+    .map(|r| r.iter().map(|v| truncate_cell(v)).collect())
+    .collect();
+  table.print(records);
+}
+
+fn maybe_planned_nothing(plan_response: &PlanResponse) {
+  if !plan_response.has_changes() {
+    println!(
+      "{} No difference detected between hosted db and cloud account",
+      dlg::warn_prefix(),
+    );
+  }
+}
+
+fn module_header(module: &str) -> String {
+  if module.is_empty() {
+    "Tables without a module".to_string()
+  } else {
+    format!("Module {}", module)
+  }
+}
+
+// Module owning each table, as recorded by the engine in the hosted db
+async fn table_modules(db: &str) -> BTreeMap<String, String> {
+  let sql = "SELECT \"table\", module FROM iasql_tables;";
+  let rows = match run_sql(db, sql).await {
+    Ok(resp) => query_rows(&resp),
+    Err(_) => return BTreeMap::new(),
+  };
+  rows
+    .iter()
+    .filter_map(|row| match (row.get("table"), row.get("module")) {
+      (Some(Value::String(table)), Some(Value::String(module))) => {
+        Some((table.clone(), module.clone()))
+      }
+      _ => None,
+    })
+    .collect()
+}
+
+// Tables with changes grouped by the module they belong to, both sorted by name. Tables
+// without a module come last.
+fn tables_by_module<'a>(
+  plan_response: &'a PlanResponse,
+  modules: &BTreeMap<String, String>,
+) -> Vec<(String, BTreeSet<&'a String>)> {
+  let mut grouped: BTreeMap<String, BTreeSet<&String>> = BTreeMap::new();
+  for action in PlanAction::all().iter() {
+    for table in plan_response.segment(*action).keys() {
+      let module = modules.get(table).cloned().unwrap_or_default();
+      grouped.entry(module).or_default().insert(table);
+    }
+  }
+  let unowned = grouped.remove("");
+  let mut grouped: Vec<(String, BTreeSet<&String>)> = grouped.into_iter().collect();
+  if let Some(tables) = unowned {
+    grouped.push(("".to_string(), tables));
+  }
+  grouped
+}
+
+fn emit_plan_counts(plan_response: &PlanResponse, modules: &BTreeMap<String, String>) {
+  let mut table = AsciiTable::default();
+  for (i, header) in REPORT_COUNT_HEADERS.iter().enumerate() {
+    table.columns.insert(
+      i,
+      Column {
+        header: header.to_string(),
+        ..Column::default()
+      },
+    );
+  }
+  let rows: Vec<Vec<String>> = report_count_rows(plan_response, modules)
+    .iter()
+    .map(|row| row.iter().map(|cell| truncate_cell(cell)).collect())
+    .collect();
+  table.print(rows);
+}
+
+// Renders the changes selected by the filter grouped by module and table, followed by
+// the number of changes per action in each table
+fn emit_plan(
+  plan_response: &PlanResponse,
+  modules: &BTreeMap<String, String>,
+  filter: &PlanFilter,
+) {
+  maybe_planned_nothing(plan_response);
+  if !plan_response.has_changes() {
+    return;
+  }
+  let filtered = plan_response.filtered(filter, modules);
+  if !filter.summary {
+    for (module, tables) in tables_by_module(&filtered, modules).iter() {
+      println!("{}", dlg::bold(&module_header(module)));
+      for table_name in tables.iter() {
+        for action in PlanAction::all().iter() {
+          if let Some(meta) = filtered.segment(*action).get(*table_name) {
+            emit_plan_segment(table_name, meta, *action);
+          }
+        }
+      }
+    }
+  }
+  if !filter.summary && filtered.lacks_previous_records() {
+    println!(
+      "{} {}",
+      dlg::warn_prefix(),
+      dlg::bold("The engine does not provide the prior values of records to update or replace, showing whole records instead of field changes"),
+    );
+  }
+  if filtered.has_changes() {
+    emit_plan_counts(&filtered, modules);
+  }
+  let hidden = plan_response.total() - filtered.total();
+  if hidden > 0 {
+    let change_text = if hidden == 1 { "change" } else { "changes" };
+    println!(
+      "{} {} {} hidden by filters",
+      dlg::warn_prefix(),
+      dlg::bold(&hidden.to_string()),
+      change_text,
+    );
+  }
+}
+
+// Columns whose values are never included in plan reports
+const SECRET_COLUMN_PATTERNS: [&str; 5] =
+  ["password", "secret", "token", "private_key", "credential"];
+
+fn is_secret_column(column: &str) -> bool {
+  let column = column.to_lowercase();
+  SECRET_COLUMN_PATTERNS.iter().any(|p| column.contains(p))
+}
+
+// Cells of a record for reports. Secret-looking values are redacted and fields that
+// change show their prior value.
+fn report_cells(meta: &PlanMeta, i: usize) -> Vec<String> {
+  let before = meta
+    .previousRecords
+    .as_ref()
+    .and_then(|p| p.get(i))
+    .filter(|b| !b.is_empty());
+  meta
+    .columns
+    .iter()
+    .zip(meta.records[i].iter())
+    .enumerate()
+    .map(|(j, (column, value))| {
+      if is_secret_column(column) {
+        return "[redacted]".to_string();
+      }
+      match before.and_then(|b| b.get(j)) {
+        Some(old) if old != value => format!("{} → {}", truncate_cell(old), truncate_cell(value)),
+        _ => truncate_cell(value),
+      }
+    })
+    .collect()
+}
+
+// Describes the changes to a table, e.g. "2 to create, 1 to delete"
+fn report_table_counts(plan_response: &PlanResponse, table_name: &str) -> String {
+  let counts: Vec<String> = PlanAction::all()
+    .iter()
+    .filter_map(|action| {
+      plan_response
+        .segment(*action)
+        .get(table_name)
+        .map(|meta| format!("{} to {}", meta.records.len(), action.verb()))
+    })
+    .collect();
+  counts.join(", ")
+}
+
+fn report_count_rows(
+  plan_response: &PlanResponse,
+  modules: &BTreeMap<String, String>,
+) -> Vec<Vec<String>> {
+  let mut rows = vec![];
+  for (module, tables) in tables_by_module(plan_response, modules).iter() {
+    for table_name in tables.iter() {
+      let mut row = vec![module.clone(), table_name.to_string()];
+      for action in PlanAction::all().iter() {
+        let count = plan_response
+          .segment(*action)
+          .get(*table_name)
+          .map(|meta| meta.records.len())
+          .unwrap_or(0);
+        row.push(count.to_string());
+      }
+      rows.push(row);
+    }
+  }
+  let mut total = vec!["Total".to_string(), "".to_string()];
+  for action in PlanAction::all().iter() {
+    total.push(plan_response.count(*action).to_string());
+  }
+  rows.push(total);
+  rows
+}
+
+const REPORT_COUNT_HEADERS: [&str; 6] =
+  ["Module", "Table", "Create", "Update", "Replace", "Delete"];
+
+fn escape_markdown(value: &str) -> String {
+  value
+    .replace('|', "\\|")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+fn markdown_table(headers: &[String], rows: &[Vec<String>]) -> String {
+  let line = |cells: &[String]| {
+    let cells: Vec<String> = cells.iter().map(|c| escape_markdown(c)).collect();
+    format!("| {} |\n", cells.join(" | "))
+  };
+  let mut table = line(headers);
+  table.push_str(&format!("|{}\n", " --- |".repeat(headers.len())));
+  for row in rows.iter() {
+    table.push_str(&line(row));
+  }
+  table
+}
+
+// GitHub-flavoured markdown report with a collapsible section per table
+fn plan_markdown(
+  db: &str,
+  plan_response: &PlanResponse,
+  modules: &BTreeMap<String, String>,
+  filter: &PlanFilter,
+) -> String {
+  let mut report = format!("### Plan for `{}`\n\n", db);
+  if !plan_response.has_changes() {
+    report.push_str("No difference detected between hosted db and cloud account\n");
+    return report;
+  }
+  let filtered = plan_response.filtered(filter, modules);
+  let headers: Vec<String> = REPORT_COUNT_HEADERS.iter().map(|h| h.to_string()).collect();
+  report.push_str(&markdown_table(
+    &headers,
+    &report_count_rows(&filtered, modules),
+  ));
+  if !filter.summary {
+    for (module, tables) in tables_by_module(&filtered, modules).iter() {
+      report.push_str(&format!("\n#### {}\n", module_header(module)));
+      for table_name in tables.iter() {
+        report.push_str(&format!(
+          "\n<details>\n<summary><code>{}</code>: {}</summary>\n",
+          table_name,
+          report_table_counts(&filtered, table_name)
+        ));
+        for action in PlanAction::all().iter() {
+          if let Some(meta) = filtered.segment(*action).get(*table_name) {
+            let rows: Vec<Vec<String>> = (0..meta.records.len())
+              .map(|i| report_cells(meta, i))
+              .collect();
+            report.push_str(&format!("\n**{}**\n\n", action.verb()));
+            report.push_str(&markdown_table(&meta.columns, &rows));
+          }
+        }
+        report.push_str("\n</details>\n");
+      }
+    }
+  }
+  let hidden = plan_response.total() - filtered.total();
+  if hidden > 0 {
+    report.push_str(&format!("\n_{} changes hidden by filters_\n", hidden));
+  }
+  report
+}
+
+fn escape_html(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+fn html_table(headers: &[String], rows: &[Vec<String>]) -> String {
+  let line = |tag: &str, cells: &[String]| {
+    let cells: Vec<String> = cells
+      .iter()
+      .map(|c| format!("<{}>{}</{}>", tag, escape_html(c), tag))
+      .collect();
+    format!("<tr>{}</tr>\n", cells.join(""))
+  };
+  let mut table = format!("<table>\n<thead>{}</thead>\n<tbody>\n", line("th", headers));
+  for row in rows.iter() {
+    table.push_str(&line("td", row));
+  }
+  table.push_str("</tbody>\n</table>\n");
+  table
+}
+
+const HTML_REPORT_STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin:.5em 0}\
+th,td{border:1px solid #ccc;padding:.25em .5em;text-align:left;font-family:monospace}\
+th{background:#f3f3f3}summary{cursor:pointer;margin:.5em 0}\
+.create{color:#1a7f37}.update{color:#9a6700}.replace{color:#8250df}.delete{color:#cf222e}";
+
+// Standalone html report with a collapsible section per table
+fn plan_html(
+  db: &str,
+  plan_response: &PlanResponse,
+  modules: &BTreeMap<String, String>,
+  filter: &PlanFilter,
+) -> String {
+  let title = format!("Plan for {}", escape_html(db));
+  let mut report = format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<p>Generated {}</p>\n",
+    title,
+    HTML_REPORT_STYLE,
+    title,
+    Utc::now().to_rfc3339()
+  );
+  if !plan_response.has_changes() {
+    report.push_str("<p>No difference detected between hosted db and cloud account</p>\n");
+  } else {
+    let filtered = plan_response.filtered(filter, modules);
+    let headers: Vec<String> = REPORT_COUNT_HEADERS.iter().map(|h| h.to_string()).collect();
+    report.push_str(&html_table(
+      &headers,
+      &report_count_rows(&filtered, modules),
+    ));
+    if !filter.summary {
+      for (module, tables) in tables_by_module(&filtered, modules).iter() {
+        report.push_str(&format!(
+          "<h2>{}</h2>\n",
+          escape_html(&module_header(module))
+        ));
+        for table_name in tables.iter() {
+          report.push_str(&format!(
+            "<details>\n<summary><code>{}</code>: {}</summary>\n",
+            escape_html(table_name),
+            report_table_counts(&filtered, table_name)
+          ));
+          for action in PlanAction::all().iter() {
+            if let Some(meta) = filtered.segment(*action).get(*table_name) {
+              let rows: Vec<Vec<String>> = (0..meta.records.len())
+                .map(|i| report_cells(meta, i))
+                .collect();
+              report.push_str(&format!(
+                "<h3 class=\"{}\">{}</h3>\n",
+                action.verb(),
+                action.verb()
+              ));
+              report.push_str(&html_table(&meta.columns, &rows));
+            }
+          }
+          report.push_str("</details>\n");
+        }
+      }
+    }
+    let hidden = plan_response.total() - filtered.total();
+    if hidden > 0 {
+      report.push_str(&format!(
+        "<p><em>{} changes hidden by filters</em></p>\n",
+        hidden
+      ));
+    }
+  }
+  report.push_str("</body>\n</html>\n");
+  report
+}
+
+fn parse_plan(resp: &str) -> PlanResponse {
+  let mut plan_response: PlanResponse = serde_json::from_str(resp).unwrap();
+  plan_response.sort_records();
+  plan_response
+}
+
+// Body of apply and sync requests, scoped to the modules and tables targeted if any
+fn operation_body(db: &str, dry_run: bool, targets: &PlanFilter) -> Value {
+  let mut body = json!({
+    "dbAlias": db,
+  });
+  if dry_run {
+    body["dryRun"] = json!(true);
+  }
+  if !targets.modules.is_empty() {
+    body["modules"] = json!(targets.modules);
+  }
+  if !targets.tables.is_empty() {
+    body["tables"] = json!(targets.tables);
+  }
+  body
+}
+
+// Engines that cannot scope apply and sync requests would silently change everything
+async fn check_targets(operation: &str, targets: &PlanFilter) {
+  if !targets.is_targeted() || engine::has_capability(SCOPE_CAPABILITY).await {
+    return;
+  }
+  eprintln!(
+    "{} {} {} {}",
+    dlg::err_prefix(),
+    dlg::bold(&format!(
+      "The engine cannot {} only some modules or tables. Run without --module and --table to {} everything",
+      operation, operation
+    )),
+    dlg::divider(),
+    dlg::red(format!("{} {}", targets.modules.join(" "), targets.tables.join(" ")).trim()),
+  );
+  exit(1);
+}
+
+// Calls apply, or sync, with dryRun set to true to get the changes it would make
+async fn post_dry_run(
+  db: &str,
+  endpoint: &str,
+  targets: &PlanFilter,
+) -> Result<PlanResponse, HttpError> {
+  let sp = ProgressBar::new_spinner();
+  sp.enable_steady_tick(10);
+  sp.set_message("Plan in progress");
+  let resp = post_v1(endpoint, operation_body(db, true, targets)).await;
+  sp.finish_and_clear();
+  resp.map(|r| parse_plan(&r))
+}
+
+async fn try_dry_run(db: &str, targets: &PlanFilter) -> Result<PlanResponse, HttpError> {
+  post_dry_run(db, "db/apply/", targets).await
+}
+
+async fn dry_run(db: &str, targets: &PlanFilter) -> PlanResponse {
+  match try_dry_run(db, targets).await {
+    Ok(plan_response) => plan_response,
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to run plan on db"),
+        dlg::divider(),
+        dlg::red(db),
+        dlg::divider(),
+        e.message
+      );
+      exit(1);
+    }
+  }
+}
+
+fn save_plan(db: &str, plan_response: &PlanResponse, targets: &PlanFilter, plan_file: &str) {
+  let saved = SavedPlan {
+    dbAlias: db.to_string(),
+    createdAt: Utc::now().to_rfc3339(),
+    hash: plan_response.hash(),
+    modules: targets.modules.clone(),
+    tables: targets.tables.clone(),
+    plan: plan_response.clone(),
+  };
+  if let Err(e) = write(plan_file, serde_json::to_string_pretty(&saved).unwrap()) {
+    eprintln!(
+      "{} {} {} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Failed to save plan file"),
+      dlg::divider(),
+      dlg::red(plan_file),
+      dlg::divider(),
+      e
+    );
+    exit(1);
+  }
+}
+
+fn read_saved_plan(plan_file: &str) -> SavedPlan {
+  let saved: Result<SavedPlan, String> = read_to_string(plan_file)
+    .map_err(|e| e.to_string())
+    .and_then(|p| serde_json::from_str(&p).map_err(|e| e.to_string()));
+  match saved {
+    Ok(saved) if saved.hash == saved.plan.hash() => saved,
+    Ok(_) => {
+      eprintln!(
+        "{} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Plan file was modified after being saved"),
+        dlg::divider(),
+        dlg::red(plan_file),
+      );
+      exit(1);
+    }
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to read plan file"),
+        dlg::divider(),
+        dlg::red(plan_file),
+        dlg::divider(),
+        e
+      );
+      exit(1);
+    }
+  }
+}
+
+// Refuses to continue unless the changes the engine computes now are the ones in the saved plan
+async fn check_saved_plan(db: &str, plan_file: &str, filter: &PlanFilter) -> PlanResponse {
+  let saved = read_saved_plan(plan_file);
+  if saved.dbAlias != db {
+    eprintln!(
+      "{} {} {} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Plan file was saved for a different db"),
+      dlg::divider(),
+      dlg::red(&saved.dbAlias),
+      dlg::divider(),
+      dlg::red(plan_file),
+    );
+    exit(1);
+  }
+  let same_targets = |saved: &[String], targets: &[String]| {
+    let mut saved = saved.to_vec();
+    let mut targets = targets.to_vec();
+    saved.sort();
+    targets.sort();
+    saved == targets
+  };
+  if !same_targets(&saved.modules, &filter.modules) || !same_targets(&saved.tables, &filter.tables)
+  {
+    let scope = format!("{} {}", saved.modules.join(" "), saved.tables.join(" "));
+    let scope = match scope.trim() {
+      "" => "all modules and tables".to_string(),
+      scope => scope.to_string(),
+    };
+    eprintln!(
+      "{} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Plan file was saved for other modules or tables. Apply it with the same --module and --table as the plan"),
+      dlg::divider(),
+      dlg::red(&scope),
+    );
+    exit(1);
+  }
+  let plan_response = dry_run(db, filter).await;
+  if plan_response.hash() != saved.hash {
+    emit_plan(&plan_response, &table_modules(db).await, filter);
+    eprintln!(
+      "{} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Changes to apply differ from the saved plan. Run `plan` again to review them"),
+      dlg::divider(),
+      dlg::red(plan_file),
+    );
+    exit(1);
+  }
+  println!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold("Changes to apply match the saved plan from"),
+    dlg::divider(),
+    dlg::green(&saved.createdAt),
+  );
+  plan_response
+}
+
+// With `detailed_exitcode` the process exits with 2 when there are pending changes,
+// 0 when there are none and 1 on errors
+pub async fn plan(
+  db: &str,
+  noninteractive: bool,
+  output: PlanOutput,
+  detailed_exitcode: bool,
+  plan_file: Option<&str>,
+  filter: &PlanFilter,
+  policy: Option<&PolicyCheck>,
+) {
+  if !noninteractive {
+    let confirmation = dlg::confirm_with_default("Press enter to confirm plan", true);
+    if !confirmation {
+      println!(
+        "{} {} {} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Did not run plan on db"),
+        dlg::divider(),
+        dlg::yellow(db)
+      );
+      exit(0);
+    }
+  }
+  // saved plans are scoped to the modules and tables `apply --plan` will target
+  let targets = match plan_file {
+    Some(_) => {
+      check_targets("apply", filter).await;
+      filter.clone()
+    }
+    None => PlanFilter::default(),
+  };
+  let plan_response = dry_run(db, &targets).await;
+  let has_changes = plan_response.has_changes();
+  if let Some(plan_file) = plan_file {
+    save_plan(db, &plan_response, &targets, plan_file);
+  }
+  match output {
+    PlanOutput::Text => emit_plan(&plan_response, &table_modules(db).await, filter),
+    PlanOutput::Json => println!("{}", serde_json::to_string_pretty(&plan_response).unwrap()),
+    PlanOutput::Markdown => {
+      let modules = table_modules(db).await;
+      print!("{}", plan_markdown(db, &plan_response, &modules, filter));
+    }
+    PlanOutput::Html => {
+      let modules = table_modules(db).await;
+      print!("{}", plan_html(db, &plan_response, &modules, filter));
+    }
+  }
+  if let Some(policy) = policy {
+    policy.enforce(&plan_response);
+  }
+  if detailed_exitcode && has_changes {
+    exit(2);
+  }
+}
+
+// Parses intervals such as `30s`, `10m` or `1h`. Plain numbers are seconds.
+pub fn parse_interval(interval: &str) -> Option<Duration> {
+  let interval = interval.trim();
+  let (value, unit) = match interval.find(|c: char| !c.is_ascii_digit()) {
+    Some(i) => interval.split_at(i),
+    None => (interval, "s"),
+  };
+  let value: u64 = value.parse().ok()?;
+  let seconds = match unit {
+    "s" => value,
+    "m" => value * 60,
+    "h" => value * 60 * 60,
+    _ => return None,
+  };
+  if seconds == 0 {
+    return None;
+  }
+  Some(Duration::from_secs(seconds))
+}
+
+fn emit_drift_report(
+  db: &str,
+  plan_response: &PlanResponse,
+  modules: &BTreeMap<String, String>,
+  output: PlanOutput,
+) {
+  let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+  let drifted = plan_response.has_changes();
+  match output {
+    PlanOutput::Json => {
+      let report = json!({
+        "timestamp": timestamp,
+        "dbAlias": db,
+        "drift": drifted,
+        "hash": plan_response.hash(),
+        "plan": plan_response,
+      });
+      println!("{}", report);
+    }
+    PlanOutput::Markdown => print!(
+      "{}",
+      plan_markdown(db, plan_response, modules, &PlanFilter::default())
+    ),
+    PlanOutput::Html => print!(
+      "{}",
+      plan_html(db, plan_response, modules, &PlanFilter::default())
+    ),
+    PlanOutput::Text if drifted => {
+      println!(
+        "{} {} {} {} {} {}",
         dlg::warn_prefix(),
-        dlg::bold("Did not remove db"),
+        dlg::bold("Drift detected in db"),
         dlg::divider(),
-        dlg::yellow(db)
+        dlg::yellow(db),
+        dlg::divider(),
+        timestamp,
       );
-      exit(0);
+      emit_plan(plan_response, modules, &PlanFilter::default());
     }
+    PlanOutput::Text => println!(
+      "{} {} {} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold("No drift detected in db"),
+      dlg::divider(),
+      dlg::green(db),
+      dlg::divider(),
+      timestamp,
+    ),
   }
-  let resp = get_v1(&format!("db/remove/{}", db)).await;
-  match &resp {
-    Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
-    Err(e) => {
-      eprintln!(
+}
+
+// Reports the changes the next `apply` would make to the cloud account without making
+// them. Exits with 2 when the db and the cloud account disagree. With `watch` the check
+// is repeated every `interval` and a report is printed whenever the drift changes.
+pub async fn drift(db: &str, output: PlanOutput, watch: Option<Duration>) {
+  let interval = match watch {
+    Some(interval) => interval,
+    None => {
+      let plan_response = dry_run(db, &PlanFilter::default()).await;
+      emit_drift_report(db, &plan_response, &table_modules(db).await, output);
+      if plan_response.has_changes() {
+        exit(2);
+      }
+      return;
+    }
+  };
+  let mut last_hash = None;
+  loop {
+    match try_dry_run(db, &PlanFilter::default()).await {
+      Ok(plan_response) => {
+        let hash = plan_response.hash();
+        if last_hash.as_ref() != Some(&hash) {
+          emit_drift_report(db, &plan_response, &table_modules(db).await, output);
+          last_hash = Some(hash);
+        }
+      }
+      // keep watching, the engine may be temporarily unavailable
+      Err(e) => eprintln!(
         "{} {} {} {} {} {}",
         dlg::err_prefix(),
-        dlg::bold("Failed to remove db"),
+        dlg::bold("Failed to check drift in db"),
         dlg::divider(),
         dlg::red(db),
         dlg::divider(),
         e.message
-      );
-      exit(1);
+      ),
     }
-  };
+    sleep(interval).await;
+  }
+}
+
+fn plan_counts(plan_response: &PlanResponse) -> String {
+  let counts: Vec<String> = PlanAction::all()
+    .iter()
+    .map(|action| {
+      format!(
+        "{} to {}",
+        dlg::bold(&plan_response.count(*action).to_string()),
+        action.label()
+      )
+    })
+    .collect();
+  counts.join(", ")
+}
+
+fn emit_plan_summary(plan_response: &PlanResponse) {
+  println!("{} {}", dlg::bold("Plan:"), plan_counts(plan_response));
 }
 
-fn emit_plan_segment(crupde: HashMap<String, PlanMeta>, mode_str: &str) {
-  for key in crupde.keys() {
-    let meta = crupde.get(key).unwrap();
-    let count = meta.records.len();
-    let record_text = if count == 1 { "record" } else { "records" };
+// Shows the changes an apply would make and asks for approval. Deleting or replacing
+// cloud resources requires typing the name of the db instead of just pressing enter.
+async fn confirm_apply(
+  db: &str,
+  preview: PlanResponse,
+  filter: &PlanFilter,
+  policy: Option<&PolicyCheck>,
+  limits: &Limits,
+) {
+  let destructive = preview.count(PlanAction::Delete) > 0 || preview.count(PlanAction::Replace) > 0;
+  emit_plan(&preview, &table_modules(db).await, filter);
+  if !preview.has_changes() {
+    exit(0);
+  }
+  check_limits(db, &preview, limits);
+  if let Some(policy) = policy {
+    policy.enforce(&preview);
+  }
+  emit_plan_summary(&preview);
+  let confirmation = if destructive {
+    let prompt = format!(
+      "Cloud resources will be deleted or replaced. Type {} to confirm apply",
+      db
+    );
+    dlg::optional_input(&prompt) == db
+  } else {
+    dlg::confirm_with_default("Press enter to confirm apply", true)
+  };
+  if !confirmation {
     println!(
-      "{} has {} {} to {}",
-      dlg::bold(key),
-      dlg::bold(&format!("{}", count)),
-      dlg::bold(record_text),
-      mode_str,
+      "{} {} {} {}",
+      dlg::warn_prefix(),
+      dlg::bold("Did not run apply on db"),
+      dlg::divider(),
+      dlg::yellow(db)
     );
-    let mut table = AsciiTable::default();
-    table.max_width = 160;
-    for (i, column) in meta.columns.iter().enumerate() {
-      table.columns.insert(
-        i,
-        Column {
-          header: column.to_string(),
-          ..Column::default()
-        },
-      );
-    }
-    table.print(meta.records.clone());
+    exit(0);
   }
 }
 
-fn maybe_planned_nothing(plan_response: &PlanResponse) {
-  if plan_response.toCreate.keys().len() == 0
-    && plan_response.toUpdate.keys().len() == 0
-    && plan_response.toReplace.keys().len() == 0
-    && plan_response.toDelete.keys().len() == 0
-  {
-    println!(
-      "{} No difference detected between hosted db and cloud account",
-      dlg::warn_prefix(),
+// Refuses to apply when the changes delete or replace more records than allowed, in
+// total or in a single table
+fn check_limits(db: &str, preview: &PlanResponse, limits: &Limits) {
+  let mut exceeded = vec![];
+  let checks = [
+    (PlanAction::Delete, limits.deletes, &limits.table_deletes),
+    (PlanAction::Replace, limits.replaces, &limits.table_replaces),
+  ];
+  for (action, max, table_max) in checks.iter() {
+    let count = preview.count(*action);
+    if let Some(max) = max {
+      if count > *max {
+        exceeded.push(format!(
+          "{} {}s, at most {} allowed",
+          count,
+          action.verb(),
+          max
+        ));
+      }
+    }
+    for (table, max) in table_max.iter() {
+      let count = preview
+        .segment(*action)
+        .get(table)
+        .map(|meta| meta.records.len())
+        .unwrap_or(0);
+      if count > *max {
+        exceeded.push(format!(
+          "{} to {} in {}, at most {} allowed",
+          count,
+          action.verb(),
+          table,
+          max
+        ));
+      }
+    }
+  }
+  if exceeded.is_empty() {
+    return;
+  }
+  for e in exceeded.iter() {
+    eprintln!(
+      "{} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Limit exceeded"),
+      dlg::divider(),
+      e,
     );
   }
+  eprintln!(
+    "{} {} {} {}",
+    dlg::err_prefix(),
+    dlg::bold("Refusing to apply destructive changes above the limits to db"),
+    dlg::divider(),
+    dlg::red(db),
+  );
+  exit(1);
 }
 
-pub async fn plan(db: &str, noninteractive: bool) {
+// Checks the changes about to be applied against the limits and the policy, asking for
+// approval first unless noninteractive. Exits instead of returning if any check fails.
+async fn approve_apply(
+  db: &str,
+  preview: PlanResponse,
+  noninteractive: bool,
+  filter: &PlanFilter,
+  policy: Option<&PolicyCheck>,
+  limits: &Limits,
+) {
   if !noninteractive {
-    let confirmation = dlg::confirm_with_default("Press enter to confirm plan", true);
-    if !confirmation {
-      println!(
-        "{} {} {} {}",
-        dlg::warn_prefix(),
-        dlg::bold("Did not run plan on db"),
-        dlg::divider(),
-        dlg::yellow(db)
-      );
-      exit(0);
-    }
+    confirm_apply(db, preview, filter, policy, limits).await;
+    return;
   }
-  let sp = ProgressBar::new_spinner();
-  sp.enable_steady_tick(10);
-  sp.set_message("Plan in progress");
-  // call apply with dryRun set to true
-  let body = json!({
-    "dbAlias": db,
-    "dryRun": true,
-  });
-  let resp = post_v1("db/apply/", body).await;
-  sp.finish_and_clear();
+  check_limits(db, &preview, limits);
+  if let Some(policy) = policy {
+    policy.enforce(&preview);
+  }
+}
+
+async fn run_apply(db: &str, targets: &PlanFilter) -> PlanResponse {
+  let body = operation_body(db, false, targets);
+  let resp = job::run("db/apply/", body, "Apply in progress").await;
   match &resp {
-    Ok(r) => {
-      let plan_response: PlanResponse = serde_json::from_str(r).unwrap();
-      maybe_planned_nothing(&plan_response);
-      emit_plan_segment(plan_response.toCreate, &dlg::green("create").to_string());
-      emit_plan_segment(plan_response.toUpdate, &dlg::yellow("update").to_string());
-      emit_plan_segment(
-        plan_response.toReplace,
-        &dlg::magenta("replace").to_string(),
-      );
-      emit_plan_segment(plan_response.toDelete, &dlg::red("delete").to_string());
-    }
+    Ok(r) => parse_plan(r),
     Err(e) => {
       eprintln!(
         "{} {} {} {} {} {}",
         dlg::err_prefix(),
-        dlg::bold("Failed to run plan on db"),
+        dlg::bold("Failed to run apply on db"),
         dlg::divider(),
         dlg::red(db),
         dlg::divider(),
@@ -372,49 +1642,105 @@ pub async fn plan(db: &str, noninteractive: bool) {
       );
       exit(1);
     }
-  };
+  }
 }
 
-pub async fn apply(db: &str, noninteractive: bool) {
-  if !noninteractive {
-    let confirmation = dlg::confirm_with_default("Press enter to confirm apply", true);
-    if !confirmation {
+// Checks that the next apply would not make any change, applying again until it does not
+// or `max_iterations` applies were made in total. Each apply again goes through the same
+// limits, policy and confirmation as the first one.
+async fn verify_apply(
+  db: &str,
+  max_iterations: usize,
+  noninteractive: bool,
+  filter: &PlanFilter,
+  policy: Option<&PolicyCheck>,
+  limits: &Limits,
+) {
+  let mut iteration = 1;
+  loop {
+    let remaining = dry_run(db, filter).await;
+    println!(
+      "{} {} {} {}",
+      dlg::bold(&format!("Iteration {}/{}", iteration, max_iterations)),
+      dlg::divider(),
+      plan_counts(&remaining),
+      dlg::gray("remaining"),
+    );
+    if !remaining.has_changes() {
       println!(
         "{} {} {} {}",
-        dlg::warn_prefix(),
-        dlg::bold("Did not run apply on db"),
+        dlg::success_prefix(),
+        dlg::bold("Cloud account converged with db"),
         dlg::divider(),
-        dlg::yellow(db)
+        dlg::green(db),
       );
-      exit(0);
+      return;
     }
-  }
-  let sp = ProgressBar::new_spinner();
-  sp.enable_steady_tick(10);
-  sp.set_message("Apply in progress");
-  let body = json!({
-    "dbAlias": db,
-  });
-  let resp = post_v1("db/apply/", body).await;
-  sp.finish_and_clear();
-  match &resp {
-    Ok(r) => {
-      let plan_response: PlanResponse = serde_json::from_str(r).unwrap();
-      maybe_planned_nothing(&plan_response);
-      emit_plan_segment(plan_response.toCreate, &dlg::green("create").to_string());
-      emit_plan_segment(plan_response.toUpdate, &dlg::yellow("update").to_string());
-      emit_plan_segment(
-        plan_response.toReplace,
-        &dlg::magenta("replace").to_string(),
+    if iteration >= max_iterations {
+      emit_plan(&remaining, &table_modules(db).await, filter);
+      let iteration_text = if iteration == 1 { "apply" } else { "applies" };
+      eprintln!(
+        "{} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold(&format!(
+          "Changes remain after {} {}. Cloud account did not converge with db",
+          iteration, iteration_text
+        )),
+        dlg::divider(),
+        dlg::red(db),
       );
-      emit_plan_segment(plan_response.toDelete, &dlg::red("delete").to_string());
-      println!("{} {}", dlg::success_prefix(), dlg::bold("Done"));
+      exit(1);
     }
+    iteration += 1;
+    approve_apply(db, remaining, noninteractive, filter, policy, limits).await;
+    run_apply(db, filter).await;
+  }
+}
+
+// Policy rules and limits are checked against the changes computed right before applying
+// them. With `verify` set to the maximum number of applies, the changes left after
+// applying are checked and applied again until there are none.
+pub async fn apply(
+  db: &str,
+  noninteractive: bool,
+  plan_file: Option<&str>,
+  filter: &PlanFilter,
+  policy: Option<&PolicyCheck>,
+  limits: &Limits,
+  verify: Option<usize>,
+) {
+  check_targets("apply", filter).await;
+  let preview = match plan_file {
+    Some(plan_file) => Some(check_saved_plan(db, plan_file, filter).await),
+    None => None,
+  };
+  if !noninteractive || policy.is_some() || !limits.is_empty() {
+    let preview = match preview {
+      Some(preview) => preview,
+      None => dry_run(db, filter).await,
+    };
+    approve_apply(db, preview, noninteractive, filter, policy, limits).await;
+  }
+  let plan_response = run_apply(db, filter).await;
+  // interactive applies already displayed the changes before confirmation
+  if noninteractive {
+    emit_plan(&plan_response, &table_modules(db).await, filter);
+  }
+  println!("{} {}", dlg::success_prefix(), dlg::bold("Done"));
+  if let Some(max_iterations) = verify {
+    verify_apply(db, max_iterations, noninteractive, filter, policy, limits).await;
+  }
+}
+
+// Changes the next `sync` would make to the db, discarding any of its unapplied changes
+async fn sync_dry_run(db: &str, targets: &PlanFilter) -> PlanResponse {
+  match post_dry_run(db, "db/sync/", targets).await {
+    Ok(plan_response) => plan_response,
     Err(e) => {
       eprintln!(
         "{} {} {} {} {} {}",
         dlg::err_prefix(),
-        dlg::bold("Failed to run apply on db"),
+        dlg::bold("Failed to preview sync on db"),
         dlg::divider(),
         dlg::red(db),
         dlg::divider(),
@@ -422,12 +1748,24 @@ pub async fn apply(db: &str, noninteractive: bool) {
       );
       exit(1);
     }
-  };
+  }
 }
 
-pub async fn sync(db: &str, noninteractive: bool) {
-  if !noninteractive {
-    let confirmation = dlg::confirm_with_default("Press enter to confirm sync", true);
+// With `dry_run` the changes a sync would make are displayed but not written to the db.
+// Interactive syncs display them before asking for confirmation.
+pub async fn sync(db: &str, noninteractive: bool, dry_run: bool, filter: &PlanFilter) {
+  check_targets("sync", filter).await;
+  if dry_run || !noninteractive {
+    let preview = sync_dry_run(db, filter).await;
+    emit_plan(&preview, &table_modules(db).await, filter);
+    if dry_run || !preview.has_changes() {
+      exit(0);
+    }
+    emit_plan_summary(&preview);
+    let confirmation = dlg::confirm_with_default(
+      "Changes in the db not applied to the cloud account will be overwritten. Press enter to confirm sync",
+      true,
+    );
     if !confirmation {
       println!(
         "{} {} {} {}",
@@ -439,25 +1777,15 @@ pub async fn sync(db: &str, noninteractive: bool) {
       exit(0);
     }
   }
-  let sp = ProgressBar::new_spinner();
-  sp.enable_steady_tick(10);
-  sp.set_message("Sync in progress");
-  let body = json!({
-    "dbAlias": db,
-  });
-  let resp = post_v1("db/sync/", body).await;
-  sp.finish_and_clear();
+  let body = operation_body(db, false, filter);
+  let resp = job::run("db/sync/", body, "Sync in progress").await;
   match &resp {
     Ok(r) => {
-      let plan_response: PlanResponse = serde_json::from_str(r).unwrap();
-      maybe_planned_nothing(&plan_response);
-      emit_plan_segment(plan_response.toCreate, &dlg::green("create").to_string());
-      emit_plan_segment(plan_response.toUpdate, &dlg::yellow("update").to_string());
-      emit_plan_segment(
-        plan_response.toReplace,
-        &dlg::magenta("replace").to_string(),
-      );
-      emit_plan_segment(plan_response.toDelete, &dlg::red("delete").to_string());
+      // interactive syncs already displayed the changes before confirmation
+      if noninteractive {
+        let plan_response = parse_plan(r);
+        emit_plan(&plan_response, &table_modules(db).await, filter);
+      }
       println!("{} {}", dlg::success_prefix(), dlg::bold("Done"));
     }
     Err(e) => {
@@ -475,7 +1803,10 @@ pub async fn sync(db: &str, noninteractive: bool) {
   };
 }
 
-fn provide_aws_region(noninteractive: bool) -> String {
+fn provide_aws_region(region_opt: Option<&str>, noninteractive: bool) -> String {
+  if let Some(region) = region_opt {
+    return region.to_string();
+  }
   let region_env = std::env::var("AWS_REGION");
   if region_env.is_ok() {
     return region_env.unwrap();
@@ -491,7 +1822,26 @@ fn provide_aws_region(noninteractive: bool) -> String {
   regions[selection].clone()
 }
 
-fn provide_aws_creds(noninteractive: bool) -> (String, String) {
+fn provide_aws_creds(profile_opt: Option<&str>, noninteractive: bool) -> (String, String) {
+  if let Some(profile) = profile_opt {
+    let all_creds = get_aws_cli_creds().unwrap_or_default();
+    return match all_creds.get(profile) {
+      Some(creds) => (
+        creds.aws_access_key_id.clone(),
+        creds.aws_secret_access_key.clone(),
+      ),
+      None => {
+        eprintln!(
+          "{} {} {} {}",
+          dlg::err_prefix(),
+          dlg::bold("Nonexistent AWS CLI named profile"),
+          dlg::divider(),
+          dlg::red(profile)
+        );
+        exit(1);
+      }
+    };
+  }
   let key_env = std::env::var("AWS_ACCESS_KEY_ID");
   let secret_env = std::env::var("AWS_SECRET_ACCESS_KEY");
   if key_env.is_ok() && secret_env.is_ok() {
@@ -584,13 +1934,13 @@ fn display_new_db(db_metadata: NewDbResponse) {
   );
 }
 
-pub async fn new(db_opt: Option<&str>, noninteractive: bool) -> String {
+pub async fn new(db_opt: Option<&str>, region_opt: Option<&str>, noninteractive: bool) -> String {
   println!(
     "{}",
     dlg::bold("Connect a cloud account to a hosted IaSQL DB...\n")
   );
-  let (access_key, secret) = provide_aws_creds(noninteractive);
-  let region = provide_aws_region(noninteractive);
+  let (access_key, secret) = provide_aws_creds(None, noninteractive);
+  let region = provide_aws_region(region_opt, noninteractive);
   let db = get_or_input_db(db_opt).await;
   let sp = ProgressBar::new_spinner();
   sp.enable_steady_tick(10);
@@ -663,8 +2013,18 @@ pub async fn import(db: &str, dump_file: &str, noninteractive: bool) {
       exit(1);
     }
   };
-  let region = provide_aws_region(noninteractive);
-  let (access_key, secret) = provide_aws_creds(noninteractive);
+  import_dump(db, dump, None, None, noninteractive).await;
+}
+
+async fn import_dump(
+  db: &str,
+  dump: &str,
+  region_opt: Option<&str>,
+  profile_opt: Option<&str>,
+  noninteractive: bool,
+) {
+  let region = provide_aws_region(region_opt, noninteractive);
+  let (access_key, secret) = provide_aws_creds(profile_opt, noninteractive);
   let sp = ProgressBar::new_spinner();
   sp.enable_steady_tick(10);
   sp.set_message("Creating a new db to manage cloud resources from a dump");
@@ -697,3 +2057,316 @@ pub async fn import(db: &str, dump_file: &str, noninteractive: bool) {
     }
   };
 }
+
+// Copies the state of a hosted db into a new hosted db by exporting it to a dump held in
+// memory that is imported using the given, or newly provided, AWS region and credentials
+pub async fn clone(
+  src_db: &str,
+  dst_db_opt: Option<&str>,
+  region_opt: Option<&str>,
+  profile_opt: Option<&str>,
+  noninteractive: bool,
+) {
+  let dst_db = get_or_input_db(dst_db_opt).await;
+  if !noninteractive {
+    let prompt = format!("Press enter to confirm clone of {} into {}", src_db, dst_db);
+    let confirmation = dlg::confirm_with_default(&prompt, true);
+    if !confirmation {
+      println!(
+        "{} {} {} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Did not clone db"),
+        dlg::divider(),
+        dlg::yellow(src_db)
+      );
+      exit(0);
+    }
+  }
+  // the dump is kept in memory, nothing of the source db is written to disk
+  let dump = export_dump(src_db, false).await;
+  import_dump(&dst_db, &dump, region_opt, profile_opt, noninteractive).await;
+}
+
+// Pairs of values to rewrite sorted so that longer values are replaced first
+fn read_value_mapping(mapping_file: &str) -> Vec<(String, String)> {
+  let mapping: Result<HashMap<String, String>, String> = read_to_string(mapping_file)
+    .map_err(|e| e.to_string())
+    .and_then(|m| serde_json::from_str(&m).map_err(|e| e.to_string()));
+  match mapping {
+    Ok(mapping) => {
+      let mut mapping: Vec<(String, String)> = mapping.into_iter().collect();
+      mapping.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)));
+      mapping
+    }
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to parse mapping file"),
+        dlg::divider(),
+        dlg::red(mapping_file),
+        dlg::divider(),
+        e
+      );
+      exit(1);
+    }
+  }
+}
+
+fn rewrite_dump(dump: &str, mapping: &[(String, String)]) -> String {
+  let mut counts: HashMap<&str, usize> = HashMap::new();
+  let rewritten = map_values(dump, |value| {
+    let mut value = value.to_string();
+    for (from, to) in mapping.iter() {
+      let matches = value.matches(from.as_str()).count();
+      if matches > 0 {
+        *counts.entry(from).or_default() += matches;
+        value = value.replace(from.as_str(), to);
+      }
+    }
+    value
+  });
+  for (from, to) in mapping.iter() {
+    match counts.get(from.as_str()) {
+      Some(count) => println!(
+        "{} {} {} {} {}",
+        dlg::success_prefix(),
+        dlg::bold(&format!("Rewrote {} occurrences of", count)),
+        dlg::yellow(from),
+        dlg::bold("with"),
+        dlg::green(to),
+      ),
+      None => println!(
+        "{} {} {} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Value to rewrite not found in dump"),
+        dlg::divider(),
+        dlg::yellow(from),
+      ),
+    }
+  }
+  rewritten
+}
+
+// Unique keys other than the `id` of the tables of a db, and the foreign keys between them
+async fn table_keys(db: &str) -> TableKeys {
+  let unique_sql = "SELECT t.relname AS \"table\", i.relname AS \"index\", a.attname AS \"column\"
+    FROM pg_index x
+    JOIN pg_class t ON t.oid = x.indrelid
+    JOIN pg_class i ON i.oid = x.indexrelid
+    JOIN pg_namespace n ON n.oid = t.relnamespace
+    JOIN LATERAL unnest(x.indkey) WITH ORDINALITY AS k(attnum, ord) ON true
+    JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = k.attnum
+    WHERE n.nspname = 'public' AND x.indisunique AND x.indpred IS NULL AND x.indexprs IS NULL
+    ORDER BY t.relname, i.relname, k.ord;";
+  let foreign_sql = "SELECT t.relname AS \"table\", a.attname AS \"column\",
+      rt.relname AS ref_table, ra.attname AS ref_column
+    FROM pg_constraint c
+    JOIN pg_class t ON t.oid = c.conrelid
+    JOIN pg_namespace n ON n.oid = t.relnamespace
+    JOIN pg_class rt ON rt.oid = c.confrelid
+    JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = c.conkey[1]
+    JOIN pg_attribute ra ON ra.attrelid = c.confrelid AND ra.attnum = c.confkey[1]
+    WHERE c.contype = 'f' AND n.nspname = 'public' AND array_length(c.conkey, 1) = 1;";
+  let rows = |resp: Result<String, HttpError>| match resp {
+    Ok(resp) => query_rows(&resp),
+    Err(e) => dlg::exit_with_cause("Failed to read the table keys of db", db, e.message),
+  };
+  let field = |row: &serde_json::Map<String, Value>, name: &str| -> String {
+    row
+      .get(name)
+      .and_then(|v| v.as_str())
+      .unwrap_or("")
+      .to_string()
+  };
+  let mut keys = TableKeys::default();
+  let mut indexes: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+  for row in rows(run_sql(db, unique_sql).await).iter() {
+    indexes
+      .entry((field(row, "table"), field(row, "index")))
+      .or_default()
+      .push(field(row, "column"));
+  }
+  for ((table, _), columns) in indexes.into_iter() {
+    if columns.iter().any(|c| c == "id") {
+      continue;
+    }
+    keys.unique.entry(table).or_default().push(columns);
+  }
+  for row in rows(run_sql(db, foreign_sql).await).iter() {
+    keys.foreign.entry(field(row, "table")).or_default().insert(
+      field(row, "column"),
+      (field(row, "ref_table"), field(row, "ref_column")),
+    );
+  }
+  keys
+}
+
+// Moves the infrastructure declared in a hosted db into another one, rewriting region and
+// account specific values along the way. An existing target db gets the records merged into
+// it on their unique keys, while a new target db, created with `create`, is imported from
+// the dump. Either way a plan is displayed so the result can be reviewed before running
+// `apply`.
+pub async fn promote(
+  src_db: &str,
+  dst_db: &str,
+  mapping_file: Option<&str>,
+  create: bool,
+  noninteractive: bool,
+) {
+  let mapping = match mapping_file {
+    Some(file) => read_value_mapping(file),
+    None => vec![],
+  };
+  let merge = get_dbs(false).await.contains(&dst_db.to_string());
+  if !merge && !create {
+    dlg::exit_with_cause(
+      "Nonexistent hosted db",
+      dst_db,
+      "use --create to create it from a full export of the source db",
+    );
+  }
+  let dump = export_dump(src_db, merge).await;
+  let dump = rewrite_dump(&dump, &mapping);
+  if !merge {
+    import_dump(dst_db, &dump, None, None, noninteractive).await;
+    plan(
+      dst_db,
+      true,
+      PlanOutput::Text,
+      false,
+      None,
+      &PlanFilter::default(),
+      None,
+    )
+    .await;
+    return;
+  }
+  let records = match Dump::parse(&dump) {
+    Ok(records) => records,
+    Err(e) => {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to parse dump of db"),
+        dlg::divider(),
+        dlg::red(src_db),
+        dlg::divider(),
+        e
+      );
+      exit(1);
+    }
+  };
+  // the target db keeps its own IaSQL metadata
+  let sql = match records.merge_sql("iasql_", &table_keys(dst_db).await) {
+    Ok(sql) => sql,
+    Err(e) => dlg::exit_with_cause("Failed to merge records into db", dst_db, e),
+  };
+  if sql.is_empty() {
+    println!(
+      "{} {} {} {}",
+      dlg::warn_prefix(),
+      dlg::bold("No records to promote from db"),
+      dlg::divider(),
+      dlg::yellow(src_db)
+    );
+    exit(0);
+  }
+  if !noninteractive {
+    let prompt = format!("Press enter to confirm merge of {} into {}", src_db, dst_db);
+    let confirmation = dlg::confirm_with_default(&prompt, true);
+    if !confirmation {
+      println!(
+        "{} {} {} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Did not promote into db"),
+        dlg::divider(),
+        dlg::yellow(dst_db)
+      );
+      exit(0);
+    }
+  }
+  let sp = ProgressBar::new_spinner();
+  sp.enable_steady_tick(10);
+  sp.set_message("Promotion in progress");
+  let resp = run_sql(dst_db, &sql).await;
+  sp.finish_and_clear();
+  if let Err(e) = resp {
+    eprintln!(
+      "{} {} {} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Failed to promote into db"),
+      dlg::divider(),
+      dlg::red(dst_db),
+      dlg::divider(),
+      e.message
+    );
+    exit(1);
+  }
+  println!("{} {}", dlg::success_prefix(), dlg::bold("Done"));
+  plan(
+    dst_db,
+    true,
+    PlanOutput::Text,
+    false,
+    None,
+    &PlanFilter::default(),
+    None,
+  )
+  .await;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn plan(update: &str) -> PlanResponse {
+    serde_json::from_str(&format!(
+      "{{\"iasqlPlanVersion\": 3, \"toCreate\": {{}}, \"toUpdate\": {{\"instance\": {}}}, \
+       \"toReplace\": {{}}, \"toDelete\": {{}}}}",
+      update
+    ))
+    .unwrap()
+  }
+
+  fn update_lines(plan: &PlanResponse) -> Vec<String> {
+    let meta = &plan.toUpdate["instance"];
+    let previous = meta.previousRecords.clone().unwrap_or_default();
+    field_change_lines(meta, &previous, PlanAction::Update)
+      .iter()
+      .map(|l| dialoguer::console::strip_ansi_codes(l).trim().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn shows_changed_fields_from_previous_records() {
+    let plan = plan(
+      "{\"columns\": [\"id\", \"size\", \"name\"], \"records\": [[\"1\", \"large\", \"web\"], [\"2\", \"small\", \"db\"]], \
+       \"previousRecords\": [[\"1\", \"small\", \"web\"], []]}",
+    );
+    assert!(!plan.lacks_previous_records());
+    assert_eq!(
+      update_lines(&plan),
+      vec![
+        "~ id=1",
+        "size: small -> large",
+        "2 unchanged fields hidden",
+        "~ id=2",
+        "prior values unknown",
+        "id: 2",
+        "size: small",
+        "name: db",
+      ]
+    );
+  }
+
+  #[test]
+  fn flags_plans_without_previous_records() {
+    let plan = plan("{\"columns\": [\"id\", \"size\"], \"records\": [[\"1\", \"large\"]]}");
+    assert!(plan.lacks_previous_records());
+    assert_eq!(
+      update_lines(&plan),
+      vec!["~ id=1", "prior values unknown", "id: 1", "size: large"]
+    );
+  }
+}
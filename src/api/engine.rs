@@ -0,0 +1,22 @@
+use once_cell::sync::OnceCell;
+
+use crate::http::get_v1;
+
+static CAPABILITIES: OnceCell<Vec<String>> = OnceCell::new();
+
+// Optional features of the hosted engine, fetched once per invocation. Engines without
+// the capabilities endpoint, or failing to answer it, support none of them.
+pub async fn capabilities() -> &'static [String] {
+  if let Some(capabilities) = CAPABILITIES.get() {
+    return capabilities;
+  }
+  let capabilities = match get_v1("capabilities").await {
+    Ok(r) => serde_json::from_str(&r).unwrap_or_default(),
+    Err(_) => vec![],
+  };
+  CAPABILITIES.get_or_init(|| capabilities)
+}
+
+pub async fn has_capability(capability: &str) -> bool {
+  capabilities().await.iter().any(|c| c == capability)
+}
@@ -0,0 +1,277 @@
+use ascii_table::{AsciiTable, Column};
+use chrono::{DateTime, Utc};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::process::exit;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::api::engine;
+use crate::dialoguer as dlg;
+use crate::http::{get_v1, post_v1, HttpError};
+use crate::interrupt;
+
+// Capability of engines able to run long operations as jobs
+const JOBS_CAPABILITY: &str = "jobs";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
+#[allow(non_snake_case)]
+pub struct Job {
+  id: String,
+  dbAlias: String,
+  operation: String,
+  // pending, running, completed or failed
+  status: String,
+  #[serde(default)]
+  phase: Option<String>,
+  startedAt: String,
+  #[serde(default)]
+  finishedAt: Option<String>,
+  // Response of the operation once completed
+  #[serde(default)]
+  result: Option<Value>,
+  #[serde(default)]
+  error: Option<String>,
+}
+
+impl Job {
+  fn is_finished(&self) -> bool {
+    self.status == "completed" || self.status == "failed"
+  }
+
+  fn elapsed(&self) -> Option<chrono::Duration> {
+    let started = DateTime::parse_from_rfc3339(&self.startedAt).ok()?;
+    let finished = match &self.finishedAt {
+      Some(f) => DateTime::parse_from_rfc3339(f).ok()?.with_timezone(&Utc),
+      None => Utc::now(),
+    };
+    Some(finished - started.with_timezone(&Utc))
+  }
+
+  // The response of the operation, as `post_v1` would have returned it
+  fn into_response(self) -> Result<String, HttpError> {
+    match self.status.as_str() {
+      "completed" => Ok(self.result.unwrap_or(Value::Null).to_string()),
+      _ => {
+        let message = format!("Job {} {}", self.id, self.status);
+        Err(HttpError {
+          status: 500,
+          message: self.error.unwrap_or(message),
+        })
+      }
+    }
+  }
+}
+
+fn format_elapsed(elapsed: Option<chrono::Duration>) -> String {
+  match elapsed {
+    Some(e) if e.num_hours() > 0 => format!("{}h{}m", e.num_hours(), e.num_minutes() % 60),
+    Some(e) if e.num_minutes() > 0 => format!("{}m{}s", e.num_minutes(), e.num_seconds() % 60),
+    Some(e) => format!("{}s", e.num_seconds().max(0)),
+    None => "-".to_string(),
+  }
+}
+
+fn exit_without_jobs() -> ! {
+  eprintln!(
+    "{} {}",
+    dlg::err_prefix(),
+    dlg::bold("The engine does not support jobs"),
+  );
+  exit(1);
+}
+
+async fn get_job(id: &str) -> Result<Job, HttpError> {
+  let resp = get_v1(&format!("job/{}", id)).await?;
+  serde_json::from_str(&resp).map_err(|e| HttpError {
+    status: 500,
+    message: e.to_string(),
+  })
+}
+
+// Polls a job until it finishes, showing its phase and elapsed time. Ctrl-C detaches
+// from the job, which keeps running in the engine.
+async fn follow(id: &str, message: &str) -> Result<String, HttpError> {
+  interrupt::set_note(Some(format!(
+    "{} {} {}",
+    dlg::bold("Detached from job, it keeps running in the engine. Follow it with"),
+    dlg::divider(),
+    dlg::yellow(&format!("iasql jobs wait {}", id)),
+  )));
+  let resp = poll(id, message).await;
+  interrupt::set_note(None);
+  resp
+}
+
+async fn poll(id: &str, message: &str) -> Result<String, HttpError> {
+  let sp = ProgressBar::new_spinner();
+  sp.enable_steady_tick(10);
+  sp.set_message(message.to_string());
+  let started = Instant::now();
+  loop {
+    let job = match get_job(id).await {
+      Ok(job) => job,
+      Err(e) => {
+        sp.finish_and_clear();
+        return Err(e);
+      }
+    };
+    if job.is_finished() {
+      sp.finish_and_clear();
+      return job.into_response();
+    }
+    let elapsed = job
+      .elapsed()
+      .or_else(|| chrono::Duration::from_std(started.elapsed()).ok());
+    sp.set_message(format!(
+      "{} {} {} {} {}",
+      message,
+      dlg::divider(),
+      job.phase.as_deref().unwrap_or(&job.status),
+      dlg::divider(),
+      format_elapsed(elapsed),
+    ));
+    sleep(POLL_INTERVAL).await;
+  }
+}
+
+// Runs an operation in the engine as a job when the engine supports them, otherwise
+// waits on a single request. Either way the response of the operation is returned.
+pub async fn run(endpoint: &str, mut body: Value, message: &str) -> Result<String, HttpError> {
+  if !engine::has_capability(JOBS_CAPABILITY).await {
+    // the engine carries on with the operation if the request is dropped
+    let operation = endpoint
+      .trim_end_matches('/')
+      .rsplit('/')
+      .next()
+      .unwrap_or(endpoint);
+    let db = body["dbAlias"].as_str().unwrap_or_default().to_string();
+    interrupt::set_note(Some(format!(
+      "{} {} {}",
+      dlg::bold(&format!(
+        "The {} may still be running in the engine. Check the state of the db with",
+        operation
+      )),
+      dlg::divider(),
+      dlg::yellow(&format!("iasql plan {}", db)),
+    )));
+    let sp = ProgressBar::new_spinner();
+    sp.enable_steady_tick(10);
+    sp.set_message(message.to_string());
+    let resp = post_v1(endpoint, body).await;
+    sp.finish_and_clear();
+    interrupt::set_note(None);
+    return resp;
+  }
+  body["job"] = Value::Bool(true);
+  let resp = post_v1(endpoint, body).await?;
+  let job: Job = serde_json::from_str(&resp).map_err(|e| HttpError {
+    status: 500,
+    message: e.to_string(),
+  })?;
+  eprintln!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold(&format!("Started {} job", job.operation)),
+    dlg::divider(),
+    dlg::green(&job.id),
+  );
+  follow(&job.id, message).await
+}
+
+async fn require_jobs() {
+  if !engine::has_capability(JOBS_CAPABILITY).await {
+    exit_without_jobs();
+  }
+}
+
+pub async fn list(db: Option<&str>) {
+  require_jobs().await;
+  let endpoint = match db {
+    Some(db) => format!(
+      "job/list?{}",
+      form_urlencoded::Serializer::new(String::new())
+        .append_pair("dbAlias", db)
+        .finish()
+    ),
+    None => "job/list".to_string(),
+  };
+  let jobs: Vec<Job> = match get_v1(&endpoint).await {
+    Ok(r) => serde_json::from_str(&r).unwrap(),
+    Err(e) => dlg::exit_with_cause("Failed to list jobs", db.unwrap_or("all dbs"), e.message),
+  };
+  if jobs.is_empty() {
+    println!("{} {}", dlg::warn_prefix(), dlg::bold("No jobs found"));
+    return;
+  }
+  let mut table = AsciiTable::default();
+  let headers = [
+    "Job",
+    "Db",
+    "Operation",
+    "Status",
+    "Phase",
+    "Started",
+    "Elapsed",
+  ];
+  for (i, header) in headers.iter().enumerate() {
+    table.columns.insert(
+      i,
+      Column {
+        header: header.to_string(),
+        ..Column::default()
+      },
+    );
+  }
+  let rows: Vec<Vec<String>> = jobs
+    .iter()
+    .map(|j| {
+      vec![
+        j.id.clone(),
+        j.dbAlias.clone(),
+        j.operation.clone(),
+        j.status.clone(),
+        j.phase.clone().unwrap_or_default(),
+        j.startedAt.clone(),
+        format_elapsed(j.elapsed()),
+      ]
+    })
+    .collect();
+  table.print(rows);
+}
+
+pub async fn show(id: &str) {
+  require_jobs().await;
+  let job = match get_job(id).await {
+    Ok(job) => job,
+    Err(e) => dlg::exit_with_cause("Failed to get job", id, e.message),
+  };
+  let fields = [
+    ("Job", job.id.clone()),
+    ("Db", job.dbAlias.clone()),
+    ("Operation", job.operation.clone()),
+    ("Status", job.status.clone()),
+    ("Phase", job.phase.clone().unwrap_or_default()),
+    ("Started", job.startedAt.clone()),
+    ("Finished", job.finishedAt.clone().unwrap_or_default()),
+    ("Elapsed", format_elapsed(job.elapsed())),
+  ];
+  for (name, value) in fields.iter() {
+    println!("{} {}", dlg::bold(&format!("{}:", name)), value);
+  }
+  if let Some(error) = &job.error {
+    println!("{} {}", dlg::bold("Error:"), dlg::red(error));
+  }
+}
+
+// Reattaches to a job, e.g. after detaching from it with Ctrl-C
+pub async fn wait(id: &str) {
+  require_jobs().await;
+  match follow(id, "Waiting for job").await {
+    Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
+    Err(e) => dlg::exit_with_cause("Job failed", id, e.message),
+  }
+}
@@ -1,2 +1,4 @@
 pub mod db;
+pub mod engine;
+pub mod job;
 pub mod module;
@@ -1,22 +1,129 @@
 use ascii_table::{AsciiTable, Column};
 use futures::future::join_all;
-use indicatif::ProgressBar;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use std::fmt::Display;
 use std::process::exit;
 
+use crate::api::job;
 use crate::dialoguer as dlg;
 use crate::http::post_v1;
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
-struct Module {
+pub struct Module {
+  pub name: String,
+  pub dependencies: Vec<String>,
+}
+
+// Version given in a module reference
+enum VersionSpec {
+  Latest,
+  Exact(Version),
+  Range(VersionReq),
+}
+
+// A module given by name, meaning its latest version, by name and exact version, or by
+// name and version range: aws_ec2, aws_ec2@0.0.1, aws_ec2@^0.1. Pre-release versions are
+// only picked when given exactly or by a range naming a pre-release.
+pub struct ModuleRef {
   name: String,
-  dependencies: Vec<String>,
+  version: VersionSpec,
+}
+
+// Name and version of a module as listed by the engine, e.g. aws_ec2@0.0.1
+pub fn split_module_name(module: &str) -> (&str, &str) {
+  module.split_once('@').unwrap_or((module, ""))
+}
+
+impl ModuleRef {
+  pub fn parse(reference: &str) -> Result<ModuleRef, String> {
+    let (name, version) = split_module_name(reference);
+    let version = match version.trim() {
+      "" | "latest" => VersionSpec::Latest,
+      v => match Version::parse(v) {
+        Ok(exact) => VersionSpec::Exact(exact),
+        Err(_) => VersionSpec::Range(VersionReq::parse(v).map_err(|e| e.to_string())?),
+      },
+    };
+    Ok(ModuleRef {
+      name: name.to_string(),
+      version,
+    })
+  }
+
+  fn matches(&self, version: &Version) -> bool {
+    match &self.version {
+      VersionSpec::Latest => version.pre.is_empty(),
+      VersionSpec::Exact(exact) => exact == version,
+      VersionSpec::Range(range) => range.matches(version),
+    }
+  }
+
+  // Highest matching version of the module among the given ones, as listed by the engine,
+  // or none if the module is not among them
+  fn resolve(&self, modules: &[String]) -> Result<Option<String>, String> {
+    let mut versions: Vec<Version> = modules
+      .iter()
+      .map(|m| split_module_name(m))
+      .filter(|(name, _)| *name == self.name)
+      .filter_map(|(_, version)| Version::parse(version).ok())
+      .collect();
+    versions.sort();
+    if versions.is_empty() {
+      return Ok(None);
+    }
+    match versions.iter().filter(|v| self.matches(v)).max() {
+      Some(version) => Ok(Some(format!("{}@{}", self.name, version))),
+      None => {
+        let available: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+        Err(format!("available versions {}", available.join(", ")))
+      }
+    }
+  }
 }
 
-async fn list_mods(db: Option<&str>) -> Vec<Module> {
+// Module references resolved against the given modules. Exits with `missing_message` on
+// the first module not among them.
+fn resolve_mods(mods: Vec<String>, modules: &[String], missing_message: &str) -> Vec<String> {
+  let mut resolved = vec![];
+  for m in mods.iter() {
+    let module_ref = match ModuleRef::parse(m) {
+      Ok(module_ref) => module_ref,
+      Err(e) => dlg::exit_with_cause("Invalid module version", m, e),
+    };
+    let module = match module_ref.resolve(modules) {
+      Ok(Some(module)) => module,
+      Ok(None) => dlg::exit_with_error(missing_message, m),
+      Err(e) => dlg::exit_with_cause("No module version matches", m, e),
+    };
+    if !resolved.contains(&module) {
+      resolved.push(module);
+    }
+  }
+  resolved
+}
+
+// Exits if the modules include two versions of the same module
+fn check_single_versions(mods: &[String]) {
+  for (i, m) in mods.iter().enumerate() {
+    let name = split_module_name(m).0;
+    if let Some(other) = mods[..i].iter().find(|o| split_module_name(o).0 == name) {
+      dlg::exit_with_error(
+        "Only one version of a module can be installed",
+        &format!("{}, {}", other, m),
+      );
+    }
+  }
+}
+
+fn installed_names(installed: &[String]) -> Vec<&str> {
+  installed.iter().map(|m| split_module_name(m).0).collect()
+}
+
+// All the available modules, or the ones installed in the db
+pub async fn list_mods(db: Option<&str>) -> Vec<Module> {
   let body = if db.is_none() {
     json!({
       "all": true,
@@ -47,32 +154,38 @@ async fn list_mods(db: Option<&str>) -> Vec<Module> {
 pub async fn list(db: Option<&str>) {
   let mut table = AsciiTable::default();
   table.max_width = 140;
-  let column = Column {
-    header: "Module Name".into(),
-    ..Column::default()
-  };
-  table.columns.insert(0, column);
-  let column = Column {
-    header: "Dependent Modules".into(),
-    ..Column::default()
-  };
-  table.columns.insert(1, column);
+  for (i, header) in ["Module Name", "Version", "Dependent Modules"]
+    .iter()
+    .enumerate()
+  {
+    let column = Column {
+      header: header.to_string(),
+      ..Column::default()
+    };
+    table.columns.insert(i, column);
+  }
   struct DisplayMod {
     name: String,
+    version: String,
     dependencies: String,
   }
   let mods: Vec<DisplayMod> = list_mods(db)
     .await
     .iter()
-    .map(|m| DisplayMod {
-      name: m.name.clone(),
-      dependencies: m.dependencies.join(", "),
+    .map(|m| {
+      let (name, version) = split_module_name(&m.name);
+      DisplayMod {
+        name: name.to_string(),
+        version: version.to_string(),
+        dependencies: m.dependencies.join(", "),
+      }
     })
     .collect();
   let mut mod_data: Vec<Vec<&dyn Display>> = vec![];
   for m in mods.iter() {
     let mut row: Vec<&dyn Display> = Vec::new();
     row.push(&m.name);
+    row.push(&m.version);
     row.push(&m.dependencies);
     mod_data.push(row);
   }
@@ -120,62 +233,12 @@ pub async fn mods_to_remove(db: &str, mods_opt: Option<Vec<String>>) -> Vec<Stri
       .collect()
   } else {
     let mods = mods_opt.unwrap();
-    // TODO: Remove this hack when the real module versioning design is complete
-    let mods: Vec<String> = mods
-      .into_iter()
-      .map(|m| {
-        if m.find('@').is_none() {
-          format!("{}@0.0.1", m)
-        } else {
-          m
-        }
-      })
-      .collect();
     // check provided mods exist
-    let inexistent = mods.iter().find(|e| !all.contains(e));
-    if inexistent.is_some() {
-      eprint!(
-        "{} {} {} {}",
-        dlg::err_prefix(),
-        dlg::bold("Nonexistent module"),
-        dlg::divider(),
-        dlg::red(inexistent.unwrap()),
-      );
-      exit(1);
-    }
-    // check provided modules are installed
-    let missing = mods.iter().find(|e| !installed.contains(e));
-    if missing.is_some() {
-      eprintln!(
-        "{} {} {} {}",
-        dlg::err_prefix(),
-        dlg::bold("Module already removed"),
-        dlg::divider(),
-        dlg::red(missing.unwrap()),
-      );
-      exit(1);
-    }
-    mods
+    resolve_mods(mods.clone(), &all, "Nonexistent module");
+    // check provided modules are installed, a bare name being the installed version
+    resolve_mods(mods, &installed, "Module already removed")
   };
-  // check no module is depended on by remaining modules
-  for md in installed_infos.into_iter() {
-    if !mods.contains(&md.name) {
-      for dmd in &md.dependencies {
-        if mods.contains(&dmd) {
-          eprintln!(
-            "{} {} {} {} {} {}",
-            dlg::err_prefix(),
-            dlg::bold("Installed module"),
-            dlg::gray(&md.name),
-            dlg::bold("depends on module selected for removal"),
-            dlg::divider(),
-            dlg::red(&dmd),
-          );
-          exit(1);
-        }
-      }
-    }
-  }
+  check_dependents(installed_infos, &mods);
   mods
 }
 
@@ -186,7 +249,12 @@ pub async fn mods_to_install(db: &str, mods_opt: Option<Vec<String>>) -> Vec<Str
   let all_infos = &res[0];
   let all: Vec<String> = all_infos.iter().map(|m| m.name.clone()).collect();
   let installed: Vec<String> = res[1].iter().map(|m| m.name.clone()).collect();
-  if all.len() == installed.len() {
+  let installed_names = installed_names(&installed);
+  let available: Vec<String> = all
+    .into_iter()
+    .filter(|m| !installed_names.contains(&split_module_name(m).0))
+    .collect();
+  if available.is_empty() {
     println!(
       "{} {}",
       dlg::warn_prefix(),
@@ -195,7 +263,6 @@ pub async fn mods_to_install(db: &str, mods_opt: Option<Vec<String>>) -> Vec<Str
     exit(0);
   };
   let mut mods = if mods_opt.is_none() {
-    let available = all.into_iter().filter(|x| !installed.contains(x)).collect();
     let idxs = dlg::multiselect(
       "Use arrows to move, space to (de)select modules and enter to submit",
       &available,
@@ -217,48 +284,96 @@ pub async fn mods_to_install(db: &str, mods_opt: Option<Vec<String>>) -> Vec<Str
       .map(|(_, e)| e)
       .collect()
   } else {
-    let mods = mods_opt.unwrap();
-    // TODO: Remove this hack when the real module versioning design is complete
-    let mods: Vec<String> = mods
-      .into_iter()
-      .map(|m| {
-        if m.find('@').is_none() {
-          format!("{}@0.0.1", m)
-        } else {
-          m
-        }
-      })
-      .collect();
-    let inexistent = mods.iter().find(|e| !all.contains(e));
-    if inexistent.is_some() {
-      eprintln!(
-        "{} {} {} {}",
-        dlg::err_prefix(),
-        dlg::bold("Module name does not exist"),
-        dlg::divider(),
-        dlg::red(inexistent.unwrap()),
-      );
-      exit(1);
-    }
-    let is_installed = mods.iter().find(|e| installed.contains(e));
-    if is_installed.is_some() {
-      eprintln!(
-        "{} {} {} {}",
-        dlg::err_prefix(),
-        dlg::bold("Module is already installed"),
-        dlg::divider(),
-        dlg::red(is_installed.unwrap()),
-      );
-      exit(1);
+    let mods = resolve_mods(
+      mods_opt.unwrap(),
+      &all_infos
+        .iter()
+        .map(|m| m.name.clone())
+        .collect::<Vec<String>>(),
+      "Module name does not exist",
+    );
+    // a module can only have one version installed
+    let is_installed = installed.iter().find(|i| {
+      mods
+        .iter()
+        .any(|m| split_module_name(m).0 == split_module_name(i).0)
+    });
+    if let Some(is_installed) = is_installed {
+      dlg::exit_with_error("Module is already installed", is_installed);
     }
     mods
   };
-  // add dependent modules not explicitly called out
-  let mut deps = vec![];
-  for md in all_infos.into_iter() {
+  check_single_versions(&mods);
+  add_dependencies(all_infos, &installed, &mut mods);
+  mods
+}
+
+// Modules the db must install and uninstall to have exactly the given modules, and the
+// modules they depend on
+pub async fn mods_to_reconcile(db: &str, mods: Vec<String>) -> (Vec<String>, Vec<String>) {
+  let futs = vec![list_mods(None), list_mods(Some(db))];
+  let res = join_all(futs).await;
+  reconcile(&res[0], &res[1], mods)
+}
+
+// Installed modules matching the references are kept even if newer versions exist, as
+// replacing them would drop their records
+fn reconcile(
+  all_infos: &[Module],
+  installed_infos: &[Module],
+  mods: Vec<String>,
+) -> (Vec<String>, Vec<String>) {
+  let all: Vec<String> = all_infos.iter().map(|m| m.name.clone()).collect();
+  let installed: Vec<String> = installed_infos.iter().map(|m| m.name.clone()).collect();
+  let mut resolved = vec![];
+  for m in mods {
+    let kept = ModuleRef::parse(&m)
+      .ok()
+      .and_then(|module_ref| module_ref.resolve(&installed).ok().flatten());
+    match kept {
+      Some(module) => resolved.push(module),
+      None => resolved.extend(resolve_mods(vec![m], &all, "Module name does not exist")),
+    }
+  }
+  let wanted = with_dependencies(all_infos, resolved);
+  check_single_versions(&wanted);
+  let to_install: Vec<String> = wanted
+    .iter()
+    .filter(|m| !installed.contains(m))
+    .cloned()
+    .collect();
+  let to_remove: Vec<String> = installed
+    .into_iter()
+    .filter(|m| !wanted.contains(m))
+    .collect();
+  check_dependents(installed_infos, &to_remove);
+  (to_install, to_remove)
+}
+
+// The modules with the ones they depend on, directly or through other modules
+fn with_dependencies(all_infos: &[Module], mods: Vec<String>) -> Vec<String> {
+  let mut closure = mods;
+  let mut i = 0;
+  while i < closure.len() {
+    if let Some(md) = all_infos.iter().find(|m| m.name == closure[i]) {
+      for dmd in md.dependencies.iter() {
+        if !closure.contains(dmd) {
+          closure.push(dmd.clone());
+        }
+      }
+    }
+    i += 1;
+  }
+  closure
+}
+
+// Adds the dependent modules not explicitly called out
+fn add_dependencies(all_infos: &[Module], installed: &[String], mods: &mut Vec<String>) {
+  let mut deps: Vec<String> = vec![];
+  for md in all_infos.iter() {
     if mods.contains(&md.name) {
       for dmd in &md.dependencies {
-        if !installed.contains(&dmd) && !mods.contains(&dmd) {
+        if !installed.contains(dmd) && !mods.contains(dmd) && !deps.contains(dmd) {
           deps.push(dmd.clone())
         }
       }
@@ -274,7 +389,28 @@ pub async fn mods_to_install(db: &str, mods_opt: Option<Vec<String>>) -> Vec<Str
     );
     mods.append(&mut deps);
   }
-  mods
+}
+
+// Exits if a module left installed depends on one of the modules to remove
+fn check_dependents(installed_infos: &[Module], mods: &[String]) {
+  for md in installed_infos.iter() {
+    if !mods.contains(&md.name) {
+      for dmd in &md.dependencies {
+        if mods.contains(dmd) {
+          eprintln!(
+            "{} {} {} {} {} {}",
+            dlg::err_prefix(),
+            dlg::bold("Installed module"),
+            dlg::gray(&md.name),
+            dlg::bold("depends on module selected for removal"),
+            dlg::divider(),
+            dlg::red(dmd),
+          );
+          exit(1);
+        }
+      }
+    }
+  }
 }
 
 pub async fn uninstall(db: &str, mods: Vec<String>, noninteractive: bool) {
@@ -331,11 +467,7 @@ pub async fn install(db: &str, mods: Vec<String>, noninteractive: bool) {
     "list": mods,
     "dbAlias": db,
   });
-  let sp = ProgressBar::new_spinner();
-  sp.enable_steady_tick(10);
-  sp.set_message("Module installation in progress");
-  let resp = post_v1("module/install", body).await;
-  sp.finish_and_clear();
+  let resp = job::run("module/install", body, "Module installation in progress").await;
   match &resp {
     Ok(_) => println!("{} {}", dlg::success_prefix(), dlg::bold("Done")),
     Err(e) => {
@@ -352,3 +484,107 @@ pub async fn install(db: &str, mods: Vec<String>, noninteractive: bool) {
     }
   };
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn modules(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+  }
+
+  fn resolve(reference: &str, names: &[&str]) -> Result<Option<String>, String> {
+    ModuleRef::parse(reference)
+      .unwrap()
+      .resolve(&modules(names))
+  }
+
+  #[test]
+  fn parses_names_versions_and_ranges() {
+    let module_ref = ModuleRef::parse("aws_ec2").unwrap();
+    assert_eq!(module_ref.name, "aws_ec2");
+    assert!(matches!(module_ref.version, VersionSpec::Latest));
+    let module_ref = ModuleRef::parse("aws_ec2@latest").unwrap();
+    assert!(matches!(module_ref.version, VersionSpec::Latest));
+    let module_ref = ModuleRef::parse("aws_ec2@0.0.1").unwrap();
+    assert!(matches!(module_ref.version, VersionSpec::Exact(v) if v == Version::new(0, 0, 1)));
+    let module_ref = ModuleRef::parse("aws_ec2@^0.1").unwrap();
+    assert!(matches!(module_ref.version, VersionSpec::Range(_)));
+    assert!(ModuleRef::parse("aws_ec2@not a version").is_err());
+  }
+
+  #[test]
+  fn resolves_the_highest_matching_version() {
+    let names = [
+      "aws_account@0.0.1",
+      "aws_ec2@0.0.1",
+      "aws_ec2@0.1.2",
+      "aws_ec2@0.1.10",
+    ];
+    assert_eq!(
+      resolve("aws_ec2", &names),
+      Ok(Some("aws_ec2@0.1.10".to_string()))
+    );
+    assert_eq!(
+      resolve("aws_ec2@0.0.1", &names),
+      Ok(Some("aws_ec2@0.0.1".to_string()))
+    );
+    assert_eq!(
+      resolve("aws_ec2@~0.1.2", &names),
+      Ok(Some("aws_ec2@0.1.10".to_string()))
+    );
+    assert_eq!(resolve("aws_rds", &names), Ok(None));
+    assert_eq!(
+      resolve("aws_ec2@^1", &names),
+      Err("available versions 0.0.1, 0.1.2, 0.1.10".to_string())
+    );
+  }
+
+  fn module(name: &str, dependencies: &[&str]) -> Module {
+    Module {
+      name: name.to_string(),
+      dependencies: modules(dependencies),
+    }
+  }
+
+  #[test]
+  fn keeps_installed_versions_matching_the_references() {
+    let all = vec![
+      module("aws_account@0.1.0", &[]),
+      module("aws_account@0.2.0", &[]),
+      module("aws_ec2@0.1.0", &["aws_account@0.1.0"]),
+      module("aws_ec2@0.1.1", &["aws_account@0.2.0"]),
+    ];
+    let installed = vec![
+      module("aws_account@0.1.0", &[]),
+      module("aws_ec2@0.1.0", &["aws_account@0.1.0"]),
+    ];
+    let (to_install, to_remove) = reconcile(&all, &installed, modules(&["aws_ec2@^0.1"]));
+    assert!(to_install.is_empty());
+    assert!(to_remove.is_empty());
+    let (to_install, to_remove) = reconcile(&all, &installed, modules(&["aws_ec2@0.1.1"]));
+    assert_eq!(to_install, modules(&["aws_ec2@0.1.1", "aws_account@0.2.0"]));
+    assert_eq!(to_remove, modules(&["aws_account@0.1.0", "aws_ec2@0.1.0"]));
+  }
+
+  #[test]
+  fn skips_pre_releases_unless_requested() {
+    let names = ["aws_ec2@0.1.0", "aws_ec2@0.2.0-beta.1"];
+    assert_eq!(
+      resolve("aws_ec2", &names),
+      Ok(Some("aws_ec2@0.1.0".to_string()))
+    );
+    assert_eq!(
+      resolve("aws_ec2@>=0.1", &names),
+      Ok(Some("aws_ec2@0.1.0".to_string()))
+    );
+    assert_eq!(
+      resolve("aws_ec2@0.2.0-beta.1", &names),
+      Ok(Some("aws_ec2@0.2.0-beta.1".to_string()))
+    );
+    assert_eq!(
+      resolve("aws_ec2@>=0.2.0-beta", &names),
+      Ok(Some("aws_ec2@0.2.0-beta.1".to_string()))
+    );
+  }
+}
@@ -0,0 +1,253 @@
+use ascii_table::{AsciiTable, Column};
+use clap::ArgMatches;
+use dialoguer::console::Term;
+use futures::stream::{self, StreamExt};
+use tokio::process::Command;
+
+use std::env;
+use std::process::exit;
+use std::time::Instant;
+
+use crate::api::db::get_dbs;
+use crate::dialoguer as dlg;
+
+// Commands changing cloud resources or dbs, confirmed once for all the dbs
+const MUTATING_COMMANDS: [&str; 3] = ["apply", "sync", "install"];
+
+struct Outcome {
+  db: String,
+  code: i32,
+  elapsed_secs: u64,
+}
+
+pub fn is_glob(pattern: &str) -> bool {
+  pattern.contains('*') || pattern.contains('?')
+}
+
+// Matches names against patterns where `*` is any sequence of characters and `?` any
+// single character
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+  match (pattern.first(), name.first()) {
+    (None, None) => true,
+    (Some('*'), _) => {
+      glob_matches(&pattern[1..], name) || (!name.is_empty() && glob_matches(pattern, &name[1..]))
+    }
+    (Some('?'), Some(_)) => glob_matches(&pattern[1..], &name[1..]),
+    (Some(p), Some(n)) if p == n => glob_matches(&pattern[1..], &name[1..]),
+    _ => false,
+  }
+}
+
+// Hosted dbs selected by `--all` or by names and globs
+async fn select_dbs(patterns: &[String], all: bool) -> Vec<String> {
+  let dbs = get_dbs(true).await;
+  if all {
+    return dbs;
+  }
+  let mut selected: Vec<String> = vec![];
+  for pattern in patterns.iter() {
+    let chars: Vec<char> = pattern.chars().collect();
+    let matching: Vec<&String> = dbs
+      .iter()
+      .filter(|db| glob_matches(&chars, &db.chars().collect::<Vec<char>>()))
+      .collect();
+    if matching.is_empty() {
+      dlg::exit_with_error("No hosted db matches", pattern);
+    }
+    for db in matching {
+      if !selected.contains(db) {
+        selected.push(db.clone());
+      }
+    }
+  }
+  selected
+}
+
+// Flags and options of each command passed on to the runs on each db. The selection of
+// dbs and `--noninteractive` are set by the batch itself.
+fn command_args(command: &str) -> (&'static [&'static str], &'static [&'static str]) {
+  match command {
+    "export" => (&["data-only"], &[]),
+    "apply" => (
+      &["allow-policy-violations", "verify", "summary"],
+      &[
+        "policy",
+        "max-deletes",
+        "max-replaces",
+        "max-iterations",
+        "action",
+        "table",
+        "module",
+      ],
+    ),
+    "plan" => (
+      &[
+        "detailed-exitcode",
+        "check",
+        "allow-policy-violations",
+        "summary",
+      ],
+      &["output", "policy", "action", "table", "module"],
+    ),
+    "sync" => (&["dry-run", "summary"], &["action", "table", "module"]),
+    _ => (&[], &[]),
+  }
+}
+
+fn forwarded_args(command: &str, matches: &ArgMatches) -> Vec<String> {
+  let (flags, options) = command_args(command);
+  let mut forwarded = vec![];
+  for flag in flags.iter().filter(|f| matches.is_present(f)) {
+    forwarded.push(format!("--{}", flag));
+  }
+  for option in options.iter() {
+    for value in matches.values_of(option).into_iter().flatten() {
+      forwarded.push(format!("--{}", option));
+      forwarded.push(value.to_string());
+    }
+  }
+  // the modules to install
+  forwarded.extend(matches.values_of_lossy("modules").unwrap_or_default());
+  forwarded
+}
+
+fn child_args(command: &str, db: &str, forwarded: &[String], interactive: bool) -> Vec<String> {
+  let mut args = vec![command.to_string()];
+  match command {
+    "install" => args.extend(vec!["--db".to_string(), db.to_string()]),
+    "export" => args.extend(vec![db.to_string(), format!("{}.sql", db)]),
+    _ => args.push(db.to_string()),
+  }
+  args.extend(forwarded.iter().cloned());
+  // each db cannot be confirmed on its own, so destructive changes are only made when
+  // the batch itself runs noninteractive
+  if interactive && command == "apply" {
+    args.extend(
+      ["--max-deletes", "0", "--max-replaces", "0"]
+        .iter()
+        .map(|a| a.to_string()),
+    );
+  }
+  args.push("--noninteractive".to_string());
+  args
+}
+
+async fn run_on_db(command: &str, db: String, forwarded: &[String], interactive: bool) -> Outcome {
+  let started = Instant::now();
+  let mut child = Command::new(env::current_exe().unwrap());
+  child.args(child_args(command, &db, forwarded, interactive));
+  if Term::stdout().is_term() {
+    child.env("CLICOLOR_FORCE", "1");
+  }
+  let (code, output) = match child.output().await {
+    Ok(output) => {
+      let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+      text.push_str(&String::from_utf8_lossy(&output.stderr));
+      (output.status.code().unwrap_or(1), text)
+    }
+    Err(e) => (1, e.to_string()),
+  };
+  // the output of each db is printed at once to keep it separate from the others
+  println!("{}", dlg::bold(&format!("==> {} <==", db)));
+  print!("{}", output);
+  println!();
+  Outcome {
+    db,
+    code,
+    elapsed_secs: started.elapsed().as_secs(),
+  }
+}
+
+fn outcome_status(command: &str, code: i32) -> String {
+  match code {
+    0 => "ok".to_string(),
+    2 if command == "plan" => "changes".to_string(),
+    _ => "failed".to_string(),
+  }
+}
+
+// Runs a command on many dbs at once, at most `parallel` at a time, each in its own
+// process. Exits with 1 if it failed for any db.
+pub async fn run(
+  command: &str,
+  matches: &ArgMatches<'_>,
+  patterns: &[String],
+  all: bool,
+  parallel: usize,
+  noninteractive: bool,
+) {
+  // every db would write the same file, and a saved plan is bound to a single db
+  if matches.is_present("out") {
+    dlg::exit_with_error("Cannot save the plans of many dbs", "--out");
+  }
+  if matches.is_present("plan") {
+    dlg::exit_with_error("Cannot apply a saved plan to many dbs", "--plan");
+  }
+  let dbs = select_dbs(patterns, all).await;
+  if !noninteractive && MUTATING_COMMANDS.contains(&command) {
+    println!("{} {}", dlg::bold("Selected dbs:"), dbs.join(", "));
+    if command == "apply" {
+      println!(
+        "{} {}",
+        dlg::warn_prefix(),
+        dlg::bold("Dbs with changes deleting or replacing cloud resources will not be applied. Use --noninteractive to apply them"),
+      );
+    }
+    let prompt = format!("Press enter to confirm {} on {} dbs", command, dbs.len());
+    if !dlg::confirm_with_default(&prompt, true) {
+      println!(
+        "{} {}",
+        dlg::warn_prefix(),
+        dlg::bold(&format!("Did not run {} on any db", command)),
+      );
+      exit(0);
+    }
+  }
+  let forwarded = forwarded_args(command, matches);
+  let mut outcomes: Vec<Outcome> = stream::iter(dbs)
+    .map(|db| run_on_db(command, db, &forwarded, !noninteractive))
+    .buffer_unordered(parallel.max(1))
+    .collect()
+    .await;
+  outcomes.sort_by(|a, b| a.db.cmp(&b.db));
+  let mut table = AsciiTable::default();
+  for (i, header) in ["Db", "Status", "Exit code", "Elapsed"].iter().enumerate() {
+    table.columns.insert(
+      i,
+      Column {
+        header: header.to_string(),
+        ..Column::default()
+      },
+    );
+  }
+  let rows: Vec<Vec<String>> = outcomes
+    .iter()
+    .map(|o| {
+      vec![
+        o.db.clone(),
+        outcome_status(command, o.code),
+        o.code.to_string(),
+        format!("{}s", o.elapsed_secs),
+      ]
+    })
+    .collect();
+  table.print(rows);
+  let failed: Vec<String> = outcomes
+    .iter()
+    .filter(|o| outcome_status(command, o.code) == "failed")
+    .map(|o| o.db.clone())
+    .collect();
+  if !failed.is_empty() {
+    dlg::exit_with_error(
+      &format!(
+        "{} failed on {} of {} dbs",
+        command,
+        failed.len(),
+        outcomes.len()
+      ),
+      &failed.join(", "),
+    );
+  }
+  // keep the detailed exit code of plans
+  exit(outcomes.iter().map(|o| o.code).max().unwrap_or(0));
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+
+use serde::Deserialize;
+
+use crate::dialoguer as dlg;
+
+const CONFIG_FILE: &str = ".iasql/config.toml";
+
+// Caps on the destructive changes an apply can make. Limits set for a db take precedence
+// over the ones set at the top of the config file:
+//
+// [limits]
+// deletes = 20
+//
+// [db.prod.limits]
+// deletes = 5
+// replaces = 2
+// table_deletes = { rds = 0 }
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+  pub deletes: Option<usize>,
+  pub replaces: Option<usize>,
+  pub table_deletes: BTreeMap<String, usize>,
+  pub table_replaces: BTreeMap<String, usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbConfig {
+  pub limits: Limits,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub limits: Limits,
+  pub db: BTreeMap<String, DbConfig>,
+}
+
+impl Limits {
+  pub fn is_empty(&self) -> bool {
+    self.deletes.is_none()
+      && self.replaces.is_none()
+      && self.table_deletes.is_empty()
+      && self.table_replaces.is_empty()
+  }
+
+  // Limits with the ones set in `other` taking precedence
+  pub fn merge(&self, other: &Limits) -> Limits {
+    let mut table_deletes = self.table_deletes.clone();
+    table_deletes.extend(other.table_deletes.clone());
+    let mut table_replaces = self.table_replaces.clone();
+    table_replaces.extend(other.table_replaces.clone());
+    Limits {
+      deletes: other.deletes.or(self.deletes),
+      replaces: other.replaces.or(self.replaces),
+      table_deletes,
+      table_replaces,
+    }
+  }
+}
+
+pub fn config_file() -> String {
+  let home = std::env::var("HOME").unwrap();
+  format!("{}/{}", home, CONFIG_FILE)
+}
+
+impl Config {
+  // An absent config file is the same as an empty one
+  pub fn load() -> Config {
+    let file_name = config_file();
+    let content = match read_to_string(&file_name) {
+      Ok(c) => c,
+      Err(_) => return Config::default(),
+    };
+    match toml::from_str(&content) {
+      Ok(config) => config,
+      Err(e) => dlg::exit_with_cause("Failed to read config file", &file_name, e),
+    }
+  }
+
+  pub fn limits(&self, db: &str) -> Limits {
+    match self.db.get(db) {
+      Some(db_config) => self.limits.merge(&db_config.limits),
+      None => self.limits.clone(),
+    }
+  }
+}
@@ -4,8 +4,24 @@ use dialoguer::{
 };
 use theme::ColorfulTheme;
 
+use std::fmt::Display;
+use std::io::{ErrorKind, Result};
+use std::process::exit;
+
+use crate::interrupt;
+
 pub mod theme;
 
+// Prompts reading single keys fail with `Interrupted` on Ctrl-C
+fn interacted<T>(result: Result<T>) -> T {
+  if let Err(e) = &result {
+    if e.kind() == ErrorKind::Interrupted {
+      interrupt::exit_interrupted();
+    }
+  }
+  result.unwrap()
+}
+
 pub fn bold(el: &str) -> StyledObject<String> {
   style(format!("{}", el)).bold()
 }
@@ -58,50 +74,82 @@ pub fn divider() -> StyledObject<String> {
   t.success_suffix.clone()
 }
 
+// Prints an error about `detail` and exits with 1
+pub fn exit_with_error(message: &str, detail: &str) -> ! {
+  eprintln!(
+    "{} {} {} {}",
+    err_prefix(),
+    bold(message),
+    divider(),
+    red(detail),
+  );
+  exit(1);
+}
+
+// Same as `exit_with_error`, followed by the cause of the error
+pub fn exit_with_cause(message: &str, detail: &str, cause: impl Display) -> ! {
+  eprintln!(
+    "{} {} {} {} {} {}",
+    err_prefix(),
+    bold(message),
+    divider(),
+    red(detail),
+    divider(),
+    cause,
+  );
+  exit(1);
+}
+
 pub fn multiselect(prompt: &str, items: &Vec<String>) -> Vec<usize> {
-  MultiSelect::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .items(items)
-    .interact()
-    .unwrap()
+  interacted(
+    MultiSelect::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .items(items)
+      .interact(),
+  )
 }
 
 pub fn select_with_default(prompt: &str, items: &Vec<String>, default: usize) -> usize {
-  Select::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .items(items)
-    .default(default)
-    .interact()
-    .unwrap()
+  interacted(
+    Select::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .items(items)
+      .default(default)
+      .interact(),
+  )
 }
 
 pub fn input_with_validation(prompt: &str, validator: impl Validator<String>) -> String {
-  Input::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .validate_with(validator)
-    .interact_text()
-    .unwrap()
+  interacted(
+    Input::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .validate_with(validator)
+      .interact_text(),
+  )
 }
 
 pub fn confirm_with_default(prompt: &str, default: bool) -> bool {
-  Confirm::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .default(default)
-    .interact()
-    .unwrap()
+  interacted(
+    Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .default(default)
+      .interact(),
+  )
 }
 
 pub fn input(prompt: &str) -> String {
-  Input::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .interact_text()
-    .unwrap()
+  interacted(
+    Input::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .interact_text(),
+  )
 }
 
 pub fn optional_input(prompt: &str) -> String {
-  Input::with_theme(&ColorfulTheme::default())
-    .with_prompt(prompt)
-    .allow_empty(true)
-    .interact_text()
-    .unwrap()
+  interacted(
+    Input::with_theme(&ColorfulTheme::default())
+      .with_prompt(prompt)
+      .allow_empty(true)
+      .interact_text(),
+  )
 }
@@ -0,0 +1,1651 @@
+use ascii_table::{AsciiTable, Column};
+use dialoguer::console::StyledObject;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{Number, Value};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::exit;
+
+use crate::api::db::PlanAction;
+use crate::dialoguer as dlg;
+
+// Parses the pg_dump-style SQL generated by `db::export` without a hosted db.
+// Only the statements that describe tables and their records are understood:
+// `CREATE TABLE`, `ADD CONSTRAINT ... PRIMARY KEY`, `COPY ... FROM stdin` and
+// `INSERT INTO`. Everything else is ignored.
+
+// Table mapping every IaSQL table to the module that owns it
+const TABLES_TABLE: &str = "iasql_tables";
+
+// Serial column IaSQL tables use as primary key
+const SURROGATE_KEY: &str = "id";
+// How many references are followed to find the unique key of a referenced record
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct DumpColumn {
+  pub name: String,
+  pub data_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DumpTable {
+  pub name: String,
+  pub columns: Vec<DumpColumn>,
+  pub primary_key: Vec<String>,
+  // Values are kept in their textual form, `None` being SQL NULL
+  pub rows: Vec<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dump {
+  pub tables: BTreeMap<String, DumpTable>,
+}
+
+// Keys of the tables of a db other than their `id`
+#[derive(Debug, Default)]
+pub struct TableKeys {
+  // Columns of each unique key of a table
+  pub unique: BTreeMap<String, Vec<Vec<String>>>,
+  // Table and column referenced by each foreign key column of a table
+  pub foreign: BTreeMap<String, BTreeMap<String, (String, String)>>,
+}
+
+impl TableKeys {
+  fn foreign_key(&self, table: &str, column: &str) -> Option<&(String, String)> {
+    self.foreign.get(table).and_then(|f| f.get(column))
+  }
+
+  // Tables referenced by a table
+  fn references<'a>(&'a self, table: &str) -> impl Iterator<Item = &'a String> {
+    self
+      .foreign
+      .get(table)
+      .into_iter()
+      .flat_map(|f| f.values().map(|(t, _)| t))
+  }
+}
+
+impl DumpTable {
+  fn new(name: &str) -> DumpTable {
+    DumpTable {
+      name: name.to_string(),
+      columns: vec![],
+      primary_key: vec![],
+      rows: vec![],
+    }
+  }
+
+  pub fn column_index(&self, column: &str) -> Option<usize> {
+    self.columns.iter().position(|c| c.name == column)
+  }
+
+  // Columns identifying a record. Data-only dumps do not carry constraints so fall back
+  // to the `id` column IaSQL tables use, or to the whole record as a last resort.
+  pub fn key_columns(&self) -> Vec<String> {
+    if !self.primary_key.is_empty() {
+      self.primary_key.clone()
+    } else if self.column_index(SURROGATE_KEY).is_some() {
+      vec![SURROGATE_KEY.to_string()]
+    } else {
+      self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+  }
+
+  pub fn row_key(&self, row: &[Option<String>]) -> String {
+    self
+      .key_columns()
+      .iter()
+      .map(|k| {
+        let value = self
+          .column_index(k)
+          .and_then(|i| row.get(i).cloned().flatten());
+        format!("{}={}", k, value.unwrap_or_else(|| "NULL".to_string()))
+      })
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+
+  // Register the columns listed by a `COPY` or `INSERT` statement, returning the position
+  // of each of them in the table so values can be stored in a consistent order
+  fn merge_columns(&mut self, columns: &[String]) -> Vec<usize> {
+    columns
+      .iter()
+      .map(|name| match self.column_index(name) {
+        Some(i) => i,
+        None => {
+          self.columns.push(DumpColumn {
+            name: name.clone(),
+            data_type: "text".to_string(),
+          });
+          for row in self.rows.iter_mut() {
+            row.push(None);
+          }
+          self.columns.len() - 1
+        }
+      })
+      .collect()
+  }
+
+  fn push_row(&mut self, positions: &[usize], values: Vec<Option<String>>) {
+    let mut row = vec![None; self.columns.len()];
+    for (pos, value) in positions.iter().zip(values) {
+      row[*pos] = value;
+    }
+    self.rows.push(row);
+  }
+}
+
+impl Dump {
+  pub fn parse(sql: &str) -> Result<Dump, String> {
+    let mut dump = Dump::default();
+    let mut lines = sql.lines().enumerate();
+    let mut statement = String::new();
+    while let Some((n, line)) = lines.next() {
+      if statement.is_empty() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("--") {
+          continue;
+        }
+        if let Some((table, columns)) = parse_copy_header(trimmed) {
+          let table = dump.table_mut(&table);
+          let positions = table.merge_columns(&columns);
+          loop {
+            let (_, row) = match lines.next() {
+              Some(l) => l,
+              None => return Err(format!("Unterminated COPY data for table {}", table.name)),
+            };
+            if row == "\\." {
+              break;
+            }
+            let values: Vec<Option<String>> = row.split('\t').map(unescape_copy_value).collect();
+            if values.len() != positions.len() {
+              return Err(format!(
+                "Line {}: expected {} values for table {} but found {}",
+                n + 1,
+                positions.len(),
+                table.name,
+                values.len()
+              ));
+            }
+            table.push_row(&positions, values);
+          }
+          continue;
+        }
+      }
+      statement.push_str(line);
+      statement.push('\n');
+      if statement_complete(&statement) {
+        dump
+          .apply_statement(statement.trim())
+          .map_err(|e| format!("Line {}: {}", n + 1, e))?;
+        statement.clear();
+      }
+    }
+    Ok(dump)
+  }
+
+  pub fn from_file(file: &str) -> Dump {
+    let sql = match read_to_string(file) {
+      Ok(sql) => sql,
+      Err(e) => {
+        eprintln!(
+          "{} {} {} {} {} {}",
+          dlg::err_prefix(),
+          dlg::bold("Failed to read dump file"),
+          dlg::divider(),
+          dlg::red(file),
+          dlg::divider(),
+          e
+        );
+        exit(1);
+      }
+    };
+    match Dump::parse(&sql) {
+      Ok(dump) => dump,
+      Err(e) => {
+        eprintln!(
+          "{} {} {} {} {} {}",
+          dlg::err_prefix(),
+          dlg::bold("Failed to parse dump file"),
+          dlg::divider(),
+          dlg::red(file),
+          dlg::divider(),
+          e
+        );
+        exit(1);
+      }
+    }
+  }
+
+  // Module owning each table, as recorded by the engine in the dump itself
+  pub fn table_modules(&self) -> BTreeMap<String, String> {
+    let mut modules = BTreeMap::new();
+    if let Some(t) = self.tables.get(TABLES_TABLE) {
+      if let (Some(ti), Some(mi)) = (t.column_index("table"), t.column_index("module")) {
+        for row in t.rows.iter() {
+          if let (Some(table), Some(module)) = (&row[ti], &row[mi]) {
+            modules.insert(table.clone(), module.clone());
+          }
+        }
+      }
+    }
+    modules
+  }
+
+  // `INSERT` statements merging every record of the dump into another db, leaving the
+  // tables starting with `skip_prefix` out. The `id` of a record is only meaningful in its
+  // own db, so records are matched on a unique key of their table instead, new records get
+  // their `id` from the target db and foreign keys are looked up by the unique key of the
+  // record they reference.
+  pub fn merge_sql(&self, skip_prefix: &str, keys: &TableKeys) -> Result<String, String> {
+    let mut sql = String::new();
+    for table in self.merge_order(skip_prefix, keys) {
+      let key = self.unique_key(table, keys)?;
+      let columns: Vec<&DumpColumn> = table
+        .columns
+        .iter()
+        .filter(|c| c.name != SURROGATE_KEY)
+        .collect();
+      let names: Vec<String> = columns.iter().map(|c| quote_identifier(&c.name)).collect();
+      let updates: Vec<String> = columns
+        .iter()
+        .filter(|c| !key.contains(&c.name))
+        .map(|c| format!("{0} = EXCLUDED.{0}", quote_identifier(&c.name)))
+        .collect();
+      let conflict = if updates.is_empty() {
+        "ON CONFLICT DO NOTHING".to_string()
+      } else {
+        let key: Vec<String> = key.iter().map(|k| quote_identifier(k)).collect();
+        format!(
+          "ON CONFLICT ({}) DO UPDATE SET {}",
+          key.join(", "),
+          updates.join(", ")
+        )
+      };
+      for row in table.rows.iter() {
+        let mut values = vec![];
+        for c in columns.iter() {
+          let value = table.column_index(&c.name).and_then(|i| row[i].clone());
+          values.push(self.merged_value(keys, table, &c.name, &value, 0)?);
+        }
+        sql.push_str(&format!(
+          "INSERT INTO {} ({}) VALUES ({}) {};\n",
+          quote_identifier(&table.name),
+          names.join(", "),
+          values.join(", "),
+          conflict
+        ));
+      }
+    }
+    Ok(sql)
+  }
+
+  // Tables to merge, each after the tables it references
+  fn merge_order<'a>(&'a self, skip_prefix: &str, keys: &TableKeys) -> Vec<&'a DumpTable> {
+    let mut pending: Vec<&DumpTable> = self
+      .tables
+      .values()
+      .filter(|t| !t.name.starts_with(skip_prefix) && !t.rows.is_empty())
+      .collect();
+    let mut ordered: Vec<&DumpTable> = vec![];
+    while !pending.is_empty() {
+      let ready = pending.iter().position(|t| {
+        keys.references(&t.name).all(|r| {
+          *r == t.name
+            || ordered.iter().any(|o| o.name == *r)
+            || !pending.iter().any(|p| p.name == *r)
+        })
+      });
+      // tables referencing each other are merged in name order
+      ordered.push(pending.remove(ready.unwrap_or(0)));
+    }
+    ordered
+  }
+
+  fn unique_key<'a>(
+    &self,
+    table: &DumpTable,
+    keys: &'a TableKeys,
+  ) -> Result<&'a Vec<String>, String> {
+    keys
+      .unique
+      .get(&table.name)
+      .and_then(|candidates| {
+        candidates
+          .iter()
+          .find(|key| key.iter().all(|k| table.column_index(k).is_some()))
+      })
+      .ok_or_else(|| {
+        format!(
+          "table {} has no unique key other than {} to match its records",
+          table.name, SURROGATE_KEY
+        )
+      })
+  }
+
+  // A value to insert, with references to the `id` of another record replaced by a lookup
+  // of that record in the target db
+  fn merged_value(
+    &self,
+    keys: &TableKeys,
+    table: &DumpTable,
+    column: &str,
+    value: &Option<String>,
+    depth: usize,
+  ) -> Result<String, String> {
+    let (id, (ref_table, ref_column)) = match (value, keys.foreign_key(&table.name, column)) {
+      (Some(id), Some(reference)) => (id, reference),
+      _ => return Ok(quote_literal(value)),
+    };
+    if ref_column != SURROGATE_KEY {
+      return Ok(quote_literal(value));
+    }
+    if depth > MAX_REFERENCE_DEPTH {
+      return Err(format!(
+        "references from table {} nest too deeply",
+        table.name
+      ));
+    }
+    let referenced = self.tables.get(ref_table).and_then(|t| {
+      let i = t.column_index(SURROGATE_KEY)?;
+      t.rows
+        .iter()
+        .find(|row| row[i].as_deref() == Some(id.as_str()))
+        .map(|row| (t, row))
+    });
+    let (ref_table, ref_row) = referenced.ok_or_else(|| {
+      format!(
+        "record {}={} of table {} referenced by {}.{} is not in the dump",
+        SURROGATE_KEY, id, ref_table, table.name, column
+      )
+    })?;
+    let mut conditions = vec![];
+    for k in self.unique_key(ref_table, keys)?.iter() {
+      let value = ref_table.column_index(k).and_then(|i| ref_row[i].clone());
+      conditions.push(match value {
+        None => format!("{} IS NULL", quote_identifier(k)),
+        Some(_) => format!(
+          "{} = {}",
+          quote_identifier(k),
+          self.merged_value(keys, ref_table, k, &value, depth + 1)?
+        ),
+      });
+    }
+    Ok(format!(
+      "(SELECT {} FROM {} WHERE {})",
+      quote_identifier(SURROGATE_KEY),
+      quote_identifier(&ref_table.name),
+      conditions.join(" AND ")
+    ))
+  }
+
+  fn table_mut(&mut self, name: &str) -> &mut DumpTable {
+    self
+      .tables
+      .entry(name.to_string())
+      .or_insert_with(|| DumpTable::new(name))
+  }
+
+  fn apply_statement(&mut self, statement: &str) -> Result<(), String> {
+    let upper = statement.to_ascii_uppercase();
+    if upper.starts_with("CREATE TABLE") {
+      let (name, columns) = parse_create_table(statement)?;
+      let table = self.table_mut(&name);
+      for column in columns {
+        match table.column_index(&column.name) {
+          Some(i) => table.columns[i].data_type = column.data_type,
+          None => {
+            table.merge_columns(std::slice::from_ref(&column.name));
+            let last = table.columns.len() - 1;
+            table.columns[last].data_type = column.data_type;
+          }
+        }
+      }
+    } else if upper.starts_with("ALTER TABLE") && upper.contains("PRIMARY KEY") {
+      let rest = strip_keyword(statement, "ALTER TABLE").unwrap_or("");
+      let rest = strip_keyword(rest, "ONLY").unwrap_or(rest);
+      let (name, _) = split_identifier(rest);
+      let start = upper.find("PRIMARY KEY").unwrap() + "PRIMARY KEY".len();
+      let columns = &statement[start..];
+      let columns = columns
+        .trim()
+        .trim_start_matches('(')
+        .split(')')
+        .next()
+        .unwrap_or("");
+      self.table_mut(&name).primary_key = columns.split(',').map(unquote_identifier).collect();
+    } else if upper.starts_with("INSERT INTO") {
+      let rest = strip_keyword(statement, "INSERT INTO").unwrap();
+      let (name, rest) = split_identifier(rest);
+      let rest = rest.trim_start();
+      let (columns, rest) = if rest.starts_with('(') {
+        let end = rest
+          .find(')')
+          .ok_or_else(|| format!("Malformed INSERT into {}", name))?;
+        let columns: Vec<String> = rest[1..end].split(',').map(unquote_identifier).collect();
+        (Some(columns), &rest[end + 1..])
+      } else {
+        (None, rest)
+      };
+      let rest = strip_keyword(rest.trim_start(), "VALUES")
+        .ok_or_else(|| format!("Unsupported INSERT into {}", name))?;
+      let tuples = split_tuples(rest.trim_end().trim_end_matches(';'))?;
+      let table = self.table_mut(&name);
+      let columns = match columns {
+        Some(c) => c,
+        None if !table.columns.is_empty() => table.columns.iter().map(|c| c.name.clone()).collect(),
+        None => return Err(format!("Unknown columns for INSERT into {}", name)),
+      };
+      let positions = table.merge_columns(&columns);
+      for tuple in tuples {
+        let values = split_values(&tuple)?
+          .iter()
+          .map(|v| parse_literal(v))
+          .collect::<Vec<Option<String>>>();
+        if values.len() != positions.len() {
+          return Err(format!(
+            "expected {} values for table {} but found {}",
+            positions.len(),
+            name,
+            values.len()
+          ));
+        }
+        table.push_row(&positions, values);
+      }
+    }
+    Ok(())
+  }
+}
+
+// Pieces of SQL text. Literals, quoted identifiers, dollar-quoted bodies and comments are
+// kept apart from the code around them so that their content is never taken for SQL.
+#[derive(Debug, PartialEq)]
+enum SqlToken<'a> {
+  Code(&'a str),
+  // `'...'` literal, or `E'...'` with backslash escapes, and its unescaped value
+  Literal {
+    raw: &'a str,
+    value: String,
+    escaped: bool,
+  },
+  // Quoted identifier or dollar-quoted string such as a function body
+  Quoted(&'a str),
+  Comment(&'a str),
+}
+
+impl SqlToken<'_> {
+  fn raw(&self) -> &str {
+    match self {
+      SqlToken::Code(s) | SqlToken::Quoted(s) | SqlToken::Comment(s) => s,
+      SqlToken::Literal { raw, .. } => raw,
+    }
+  }
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+// Tag of a dollar quote such as `$$` or `$body$` starting the text
+fn dollar_tag(s: &str) -> Option<&str> {
+  let bytes = s.as_bytes();
+  let end = bytes[1..]
+    .iter()
+    .position(|b| !is_identifier_byte(*b) || *b == b'$')?
+    + 1;
+  if bytes[end] != b'$' || (end > 1 && bytes[1].is_ascii_digit()) {
+    return None;
+  }
+  Some(&s[..=end])
+}
+
+// Length of the `'...'` literal starting the text, if it is terminated
+fn literal_len(s: &str, escaped: bool) -> Option<usize> {
+  let bytes = s.as_bytes();
+  let mut i = 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' if escaped => i += 2,
+      b'\'' if bytes.get(i + 1) == Some(&b'\'') => i += 2,
+      b'\'' => return Some(i + 1),
+      _ => i += 1,
+    }
+  }
+  None
+}
+
+// Splits SQL into tokens. The flag is set when the text ends within a literal, a quote
+// or a block comment.
+fn tokenize(sql: &str) -> (Vec<SqlToken<'_>>, bool) {
+  let bytes = sql.as_bytes();
+  let mut tokens = vec![];
+  let mut code_start = 0;
+  let mut i = 0;
+  while i < bytes.len() {
+    let rest = &sql[i..];
+    let after_identifier = i > 0 && is_identifier_byte(bytes[i - 1]);
+    let (len, token) = match bytes[i] {
+      b'\'' => {
+        let escaped = after_identifier
+          && matches!(bytes[i - 1], b'E' | b'e')
+          && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+        let len = literal_len(rest, escaped);
+        let raw = &rest[..len.unwrap_or(rest.len())];
+        let value = raw[1..raw.len() - usize::from(len.is_some())].replace("''", "'");
+        let value = if escaped {
+          unescape_backslashes(&value)
+        } else {
+          value
+        };
+        (
+          len,
+          SqlToken::Literal {
+            raw,
+            value,
+            escaped,
+          },
+        )
+      }
+      b'"' => {
+        let len = rest[1..].find('"').map(|e| e + 2);
+        (len, SqlToken::Quoted(&rest[..len.unwrap_or(rest.len())]))
+      }
+      b'$' if !after_identifier && dollar_tag(rest).is_some() => {
+        let tag = dollar_tag(rest).unwrap();
+        let len = rest[tag.len()..].find(tag).map(|e| e + 2 * tag.len());
+        (len, SqlToken::Quoted(&rest[..len.unwrap_or(rest.len())]))
+      }
+      b'-' if rest.starts_with("--") => {
+        // line comments end with the line or the text
+        let len = rest.find('\n').unwrap_or(rest.len());
+        (Some(len), SqlToken::Comment(&rest[..len]))
+      }
+      b'/' if rest.starts_with("/*") => {
+        let len = rest[2..].find("*/").map(|e| e + 4);
+        (len, SqlToken::Comment(&rest[..len.unwrap_or(rest.len())]))
+      }
+      _ => {
+        i += 1;
+        continue;
+      }
+    };
+    if code_start < i {
+      tokens.push(SqlToken::Code(&sql[code_start..i]));
+    }
+    tokens.push(token);
+    match len {
+      Some(len) => i += len,
+      None => return (tokens, true),
+    }
+    code_start = i;
+  }
+  if code_start < bytes.len() {
+    tokens.push(SqlToken::Code(&sql[code_start..]));
+  }
+  (tokens, false)
+}
+
+// A statement is complete once it ends with a semicolon outside of any literal, quote or
+// comment
+fn statement_complete(statement: &str) -> bool {
+  let (tokens, unterminated) = tokenize(statement);
+  let mut last = ' ';
+  for token in tokens.iter() {
+    match token {
+      SqlToken::Code(code) => {
+        if let Some(c) = code.trim_end().chars().last() {
+          last = c;
+        }
+      }
+      SqlToken::Literal { .. } | SqlToken::Quoted(_) => last = '\'',
+      SqlToken::Comment(_) => {}
+    }
+  }
+  !unterminated && last == ';'
+}
+
+fn strip_keyword<'a>(s: &'a str, keyword: &str) -> Option<&'a str> {
+  let s = s.trim_start();
+  if s.len() >= keyword.len() && s[..keyword.len()].eq_ignore_ascii_case(keyword) {
+    Some(&s[keyword.len()..])
+  } else {
+    None
+  }
+}
+
+// Removes quotes and the schema from an identifier such as `public."order"`
+fn unquote_identifier(identifier: &str) -> String {
+  let mut in_quotes = false;
+  let mut start = 0;
+  let identifier = identifier.trim();
+  for (i, c) in identifier.char_indices() {
+    if c == '"' {
+      in_quotes = !in_quotes;
+    } else if c == '.' && !in_quotes {
+      start = i + 1;
+    }
+  }
+  identifier[start..].trim_matches('"').replace("\"\"", "\"")
+}
+
+// Splits the leading, possibly quoted and schema-qualified, identifier from the rest
+fn split_identifier(s: &str) -> (String, &str) {
+  let s = s.trim_start();
+  let mut in_quotes = false;
+  for (i, c) in s.char_indices() {
+    if c == '"' {
+      in_quotes = !in_quotes;
+    } else if !in_quotes && (c.is_whitespace() || c == '(' || c == ';') {
+      return (unquote_identifier(&s[..i]), &s[i..]);
+    }
+  }
+  (unquote_identifier(s), "")
+}
+
+fn parse_copy_header(line: &str) -> Option<(String, Vec<String>)> {
+  let rest = strip_keyword(line, "COPY")?;
+  if !line.to_ascii_uppercase().ends_with("FROM STDIN;") {
+    return None;
+  }
+  let (name, rest) = split_identifier(rest);
+  let rest = rest.trim_start();
+  let columns = if rest.starts_with('(') {
+    let end = rest.find(')')?;
+    rest[1..end].split(',').map(unquote_identifier).collect()
+  } else {
+    vec![]
+  };
+  Some((name, columns))
+}
+
+fn parse_create_table(statement: &str) -> Result<(String, Vec<DumpColumn>), String> {
+  let rest = strip_keyword(statement, "CREATE TABLE").unwrap();
+  let (name, rest) = split_identifier(rest);
+  let start = rest
+    .find('(')
+    .ok_or_else(|| format!("Malformed CREATE TABLE {}", name))?;
+  let end = rest
+    .rfind(')')
+    .ok_or_else(|| format!("Malformed CREATE TABLE {}", name))?;
+  let mut columns = vec![];
+  for definition in split_values(&rest[start + 1..end])? {
+    let definition = definition.trim();
+    let upper = definition.to_ascii_uppercase();
+    if upper.starts_with("CONSTRAINT") || upper.starts_with("PRIMARY KEY") {
+      continue;
+    }
+    let (column, rest) = split_identifier(definition);
+    // The type goes up to the first column constraint keyword
+    let mut data_type = vec![];
+    for word in rest.split_whitespace() {
+      match word.to_ascii_uppercase().as_str() {
+        "NOT" | "NULL" | "DEFAULT" | "COLLATE" | "CONSTRAINT" | "GENERATED" => break,
+        _ => data_type.push(word),
+      }
+    }
+    columns.push(DumpColumn {
+      name: column,
+      data_type: data_type.join(" "),
+    });
+  }
+  Ok((name, columns))
+}
+
+// Splits the `(...), (...)` tuples of an `INSERT` statement
+fn split_tuples(s: &str) -> Result<Vec<String>, String> {
+  let mut tuples = vec![];
+  let mut depth = 0;
+  let mut current = String::new();
+  let (tokens, unterminated) = tokenize(s);
+  for token in tokens.iter() {
+    let code = match token {
+      SqlToken::Code(code) => code,
+      token => {
+        if depth > 0 {
+          current.push_str(token.raw());
+        }
+        continue;
+      }
+    };
+    for c in code.chars() {
+      match c {
+        '(' => {
+          if depth > 0 {
+            current.push(c);
+          }
+          depth += 1;
+        }
+        ')' => {
+          depth -= 1;
+          if depth == 0 {
+            tuples.push(current.clone());
+            current.clear();
+          } else {
+            current.push(c);
+          }
+        }
+        _ if depth > 0 => current.push(c),
+        _ => {}
+      }
+    }
+  }
+  if depth != 0 || unterminated {
+    return Err("Unbalanced parentheses in INSERT statement".to_string());
+  }
+  Ok(tuples)
+}
+
+// Splits a comma separated list ignoring commas within literals or parentheses
+fn split_values(s: &str) -> Result<Vec<String>, String> {
+  let mut values = vec![];
+  let mut depth = 0;
+  let mut current = String::new();
+  let (tokens, unterminated) = tokenize(s);
+  if unterminated {
+    return Err("Unterminated string literal".to_string());
+  }
+  for token in tokens.iter() {
+    let code = match token {
+      SqlToken::Code(code) => code,
+      token => {
+        current.push_str(token.raw());
+        continue;
+      }
+    };
+    for c in code.chars() {
+      match c {
+        '(' | '[' => depth += 1,
+        ')' | ']' => depth -= 1,
+        ',' if depth == 0 => {
+          values.push(current.trim().to_string());
+          current.clear();
+          continue;
+        }
+        _ => {}
+      }
+      current.push(c);
+    }
+  }
+  if !current.trim().is_empty() {
+    values.push(current.trim().to_string());
+  }
+  Ok(values)
+}
+
+// Converts a SQL literal from an `INSERT` statement to its textual value
+fn parse_literal(literal: &str) -> Option<String> {
+  let literal = literal.trim();
+  let (escaped, literal) = if literal.starts_with("E'") || literal.starts_with("e'") {
+    (true, &literal[1..])
+  } else {
+    (false, literal)
+  };
+  if let Some(stripped) = literal.strip_prefix('\'') {
+    // drop any trailing `::type` cast
+    let end = stripped.rfind('\'').unwrap_or(stripped.len());
+    let value = stripped[..end].replace("''", "'");
+    return Some(if escaped {
+      unescape_backslashes(&value)
+    } else {
+      value
+    });
+  }
+  let value = match literal.find("::") {
+    Some(i) => literal[..i].trim(),
+    None => literal,
+  };
+  if value.eq_ignore_ascii_case("NULL") {
+    return None;
+  }
+  Some(value.to_string())
+}
+
+fn unescape_copy_value(value: &str) -> Option<String> {
+  if value == "\\N" {
+    None
+  } else {
+    Some(unescape_backslashes(value))
+  }
+}
+
+fn unescape_backslashes(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  let mut chars = value.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') => out.push('\n'),
+      Some('t') => out.push('\t'),
+      Some('r') => out.push('\r'),
+      Some('b') => out.push('\u{8}'),
+      Some('f') => out.push('\u{c}'),
+      Some('v') => out.push('\u{b}'),
+      Some(d) if d.is_digit(8) => {
+        let mut code = d.to_digit(8).unwrap();
+        for _ in 0..2 {
+          match chars.peek().and_then(|c| c.to_digit(8)) {
+            Some(n) => {
+              code = code * 8 + n;
+              chars.next();
+            }
+            None => break,
+          }
+        }
+        out.push(std::char::from_u32(code).unwrap_or('?'));
+      }
+      Some(other) => out.push(other),
+      None => out.push('\\'),
+    }
+  }
+  out
+}
+
+fn escape_copy_value(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for c in value.chars() {
+    match c {
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\t' => out.push_str("\\t"),
+      '\r' => out.push_str("\\r"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+pub fn quote_literal(value: &Option<String>) -> String {
+  match value {
+    Some(v) => format!("'{}'", v.replace('\'', "''")),
+    None => "NULL".to_string(),
+  }
+}
+
+pub fn quote_identifier(identifier: &str) -> String {
+  format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+// Rewrites every string literal found after the `VALUES` keyword of an `INSERT` statement
+fn map_insert_literals(statement: &str, f: &mut impl FnMut(&str) -> String) -> String {
+  let mut out = String::with_capacity(statement.len());
+  let mut in_values = false;
+  for token in tokenize(statement).0 {
+    match token {
+      SqlToken::Code(code) => {
+        in_values = in_values || code.to_ascii_uppercase().contains("VALUES");
+        out.push_str(code);
+      }
+      SqlToken::Literal { value, escaped, .. } if in_values => {
+        let value = f(&value).replace('\'', "''");
+        let value = if escaped {
+          value.replace('\\', "\\\\")
+        } else {
+          value
+        };
+        out.push('\'');
+        out.push_str(&value);
+        out.push('\'');
+      }
+      token => out.push_str(token.raw()),
+    }
+  }
+  out
+}
+
+// Applies `f` to every non-NULL value of the `COPY` data and the string literals of the
+// `INSERT` statements in a dump, leaving the rest of the SQL untouched
+pub fn map_values(sql: &str, mut f: impl FnMut(&str) -> String) -> String {
+  let mut out = String::with_capacity(sql.len());
+  let mut in_copy = false;
+  let mut statement = String::new();
+  for line in sql.lines() {
+    if in_copy {
+      if line == "\\." {
+        in_copy = false;
+        out.push_str(line);
+      } else {
+        let fields: Vec<String> = line
+          .split('\t')
+          .map(|field| match unescape_copy_value(field) {
+            Some(value) => escape_copy_value(&f(&value)),
+            None => field.to_string(),
+          })
+          .collect();
+        out.push_str(&fields.join("\t"));
+      }
+      out.push('\n');
+      continue;
+    }
+    if statement.is_empty() {
+      let trimmed = line.trim();
+      if trimmed.is_empty() || trimmed.starts_with("--") || parse_copy_header(trimmed).is_some() {
+        in_copy = parse_copy_header(trimmed).is_some();
+        out.push_str(line);
+        out.push('\n');
+        continue;
+      }
+    }
+    statement.push_str(line);
+    statement.push('\n');
+    if statement_complete(&statement) {
+      if statement
+        .trim_start()
+        .to_ascii_uppercase()
+        .starts_with("INSERT INTO")
+      {
+        out.push_str(&map_insert_literals(&statement, &mut f));
+      } else {
+        out.push_str(&statement);
+      }
+      statement.clear();
+    }
+  }
+  out.push_str(&statement);
+  out
+}
+
+fn display_value(value: &Option<String>) -> String {
+  match value {
+    Some(v) => v.clone(),
+    None => "NULL".to_string(),
+  }
+}
+
+struct FieldChange {
+  column: String,
+  before: String,
+  after: String,
+}
+
+struct TableDiff {
+  table: String,
+  columns: Vec<String>,
+  added: Vec<Vec<String>>,
+  removed: Vec<Vec<String>>,
+  // record key and the fields that changed
+  changed: Vec<(String, Vec<FieldChange>)>,
+}
+
+fn diff_table(old: Option<&DumpTable>, new: Option<&DumpTable>) -> TableDiff {
+  let empty = DumpTable::new(new.or(old).map(|t| t.name.as_str()).unwrap_or(""));
+  let old = old.unwrap_or(&empty);
+  let new = new.unwrap_or(&empty);
+  let mut columns: Vec<String> = new.columns.iter().map(|c| c.name.clone()).collect();
+  for c in old.columns.iter() {
+    if !columns.contains(&c.name) {
+      columns.push(c.name.clone());
+    }
+  }
+  let by_key = |t: &DumpTable| -> BTreeMap<String, BTreeMap<String, String>> {
+    t.rows
+      .iter()
+      .map(|row| {
+        let fields = t
+          .columns
+          .iter()
+          .zip(row.iter())
+          .map(|(c, v)| (c.name.clone(), display_value(v)))
+          .collect();
+        (t.row_key(row), fields)
+      })
+      .collect()
+  };
+  let old_rows = by_key(old);
+  let new_rows = by_key(new);
+  let as_record = |fields: &BTreeMap<String, String>| -> Vec<String> {
+    columns
+      .iter()
+      .map(|c| fields.get(c).cloned().unwrap_or_default())
+      .collect()
+  };
+  let mut diff = TableDiff {
+    table: new.name.clone(),
+    columns: columns.clone(),
+    added: vec![],
+    removed: vec![],
+    changed: vec![],
+  };
+  for (key, fields) in new_rows.iter() {
+    match old_rows.get(key) {
+      None => diff.added.push(as_record(fields)),
+      Some(old_fields) => {
+        let changes: Vec<FieldChange> = columns
+          .iter()
+          .filter_map(|c| {
+            let before = old_fields.get(c).cloned().unwrap_or_default();
+            let after = fields.get(c).cloned().unwrap_or_default();
+            if before != after {
+              Some(FieldChange {
+                column: c.clone(),
+                before,
+                after,
+              })
+            } else {
+              None
+            }
+          })
+          .collect();
+        if !changes.is_empty() {
+          diff.changed.push((key.clone(), changes));
+        }
+      }
+    }
+  }
+  for (key, fields) in old_rows.iter() {
+    if !new_rows.contains_key(key) {
+      diff.removed.push(as_record(fields));
+    }
+  }
+  diff
+}
+
+// Dumps are compared as they are, so their records are added, changed or removed rather
+// than to create, update or delete like in a plan
+fn change_label(action: PlanAction) -> StyledObject<String> {
+  action.styled(match action {
+    PlanAction::Create => "added",
+    PlanAction::Delete => "removed",
+    _ => "changed",
+  })
+}
+
+fn emit_records(table: &str, columns: &[String], records: Vec<Vec<String>>, action: PlanAction) {
+  if records.is_empty() {
+    return;
+  }
+  let count = records.len();
+  let record_text = if count == 1 { "record" } else { "records" };
+  println!(
+    "{} has {} {} {}",
+    dlg::bold(table),
+    dlg::bold(&format!("{}", count)),
+    dlg::bold(record_text),
+    change_label(action),
+  );
+  let mut ascii_table = AsciiTable {
+    max_width: 160,
+    ..AsciiTable::default()
+  };
+  for (i, column) in columns.iter().enumerate() {
+    ascii_table.columns.insert(
+      i,
+      Column {
+        header: column.to_string(),
+        ..Column::default()
+      },
+    );
+  }
+  ascii_table.print(records);
+}
+
+fn emit_changes(table: &str, changed: &[(String, Vec<FieldChange>)]) {
+  if changed.is_empty() {
+    return;
+  }
+  let count = changed.len();
+  let record_text = if count == 1 { "record" } else { "records" };
+  println!(
+    "{} has {} {} {}",
+    dlg::bold(table),
+    dlg::bold(&format!("{}", count)),
+    dlg::bold(record_text),
+    change_label(PlanAction::Update),
+  );
+  for (key, changes) in changed.iter() {
+    println!("  {} {}", change_label(PlanAction::Update), dlg::bold(key));
+    for change in changes.iter() {
+      println!(
+        "      {}: {} -> {}",
+        change.column,
+        PlanAction::Delete.styled(&change.before),
+        PlanAction::Create.styled(&change.after),
+      );
+    }
+  }
+}
+
+// Compares two dumps generated by `export` and displays the records added, removed
+// and changed between them grouped by module and table
+pub fn diff(old_file: &str, new_file: &str) {
+  let old = Dump::from_file(old_file);
+  let new = Dump::from_file(new_file);
+  let mut modules = old.table_modules();
+  modules.append(&mut new.table_modules());
+  let names: BTreeSet<&String> = old.tables.keys().chain(new.tables.keys()).collect();
+  let mut grouped: BTreeMap<String, Vec<TableDiff>> = BTreeMap::new();
+  for name in names {
+    let diff = diff_table(old.tables.get(name), new.tables.get(name));
+    if diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty() {
+      continue;
+    }
+    let module = modules.get(name).cloned().unwrap_or_default();
+    grouped.entry(module).or_default().push(diff);
+  }
+  if grouped.is_empty() {
+    println!(
+      "{} No difference detected between dumps",
+      dlg::warn_prefix(),
+    );
+    return;
+  }
+  let (mut added, mut changed, mut removed) = (0, 0, 0);
+  for (module, diffs) in grouped.into_iter() {
+    let header = if module.is_empty() {
+      "Tables without a module".to_string()
+    } else {
+      format!("Module {}", module)
+    };
+    println!("{}", dlg::bold(&header));
+    for diff in diffs.into_iter() {
+      added += diff.added.len();
+      changed += diff.changed.len();
+      removed += diff.removed.len();
+      emit_records(&diff.table, &diff.columns, diff.added, PlanAction::Create);
+      emit_changes(&diff.table, &diff.changed);
+      emit_records(&diff.table, &diff.columns, diff.removed, PlanAction::Delete);
+    }
+  }
+  println!(
+    "{} {} {}, {} {}, {} {}",
+    dlg::success_prefix(),
+    dlg::bold(&added.to_string()),
+    change_label(PlanAction::Create),
+    dlg::bold(&changed.to_string()),
+    change_label(PlanAction::Update),
+    dlg::bold(&removed.to_string()),
+    change_label(PlanAction::Delete),
+  );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConvertFormat {
+  Json,
+  Ndjson,
+  Csv,
+}
+
+impl ConvertFormat {
+  pub fn parse(format: &str) -> Option<ConvertFormat> {
+    match format {
+      "json" => Some(ConvertFormat::Json),
+      "ndjson" => Some(ConvertFormat::Ndjson),
+      "csv" => Some(ConvertFormat::Csv),
+      _ => None,
+    }
+  }
+
+  fn extension(&self) -> &'static str {
+    match self {
+      ConvertFormat::Json => "json",
+      ConvertFormat::Ndjson => "ndjson",
+      ConvertFormat::Csv => "csv",
+    }
+  }
+}
+
+// Parses a one dimensional postgres array literal such as `{a,"b c",NULL}`
+fn parse_array(value: &str) -> Option<Vec<Value>> {
+  let inner = value.strip_prefix('{')?.strip_suffix('}')?;
+  let mut items = vec![];
+  if inner.is_empty() {
+    return Some(items);
+  }
+  let mut chars = inner.chars().peekable();
+  loop {
+    let mut item = String::new();
+    let quoted = chars.peek() == Some(&'"');
+    if quoted {
+      chars.next();
+      loop {
+        match chars.next()? {
+          '\\' => item.push(chars.next()?),
+          '"' => break,
+          c => item.push(c),
+        }
+      }
+    } else {
+      while let Some(c) = chars.peek() {
+        match c {
+          ',' => break,
+          '{' | '"' => return None,
+          _ => item.push(chars.next().unwrap()),
+        }
+      }
+    }
+    if !quoted && item.eq_ignore_ascii_case("NULL") {
+      items.push(Value::Null);
+    } else {
+      items.push(Value::String(item));
+    }
+    match chars.next() {
+      Some(',') => continue,
+      None => return Some(items),
+      Some(_) => return None,
+    }
+  }
+}
+
+// Converts the textual value of a column to JSON according to the column type
+fn typed_value(data_type: &str, value: &Option<String>) -> Value {
+  let value = match value {
+    Some(v) => v,
+    None => return Value::Null,
+  };
+  let data_type = data_type.to_ascii_lowercase();
+  let as_string = || Value::String(value.clone());
+  if data_type.ends_with("[]") {
+    return parse_array(value)
+      .map(Value::Array)
+      .unwrap_or_else(as_string);
+  }
+  match data_type.split('(').next().unwrap_or("").trim() {
+    "smallint" | "integer" | "int" | "int2" | "int4" | "int8" | "bigint" | "serial"
+    | "bigserial" => value
+      .parse::<i64>()
+      .map(|n| Value::Number(n.into()))
+      .unwrap_or_else(|_| as_string()),
+    "real" | "double precision" | "float4" | "float8" | "numeric" | "decimal" => value
+      .parse::<f64>()
+      .ok()
+      .and_then(Number::from_f64)
+      .map(Value::Number)
+      .unwrap_or_else(as_string),
+    "boolean" | "bool" => match value.as_str() {
+      "t" | "true" => Value::Bool(true),
+      "f" | "false" => Value::Bool(false),
+      _ => as_string(),
+    },
+    "json" | "jsonb" => serde_json::from_str(value).unwrap_or_else(|_| as_string()),
+    _ => as_string(),
+  }
+}
+
+fn csv_value(value: &Value) -> String {
+  match value {
+    Value::Null => "".to_string(),
+    Value::String(s) => s.clone(),
+    v => v.to_string(),
+  }
+}
+
+// A record as a JSON object with its fields in the column order of the dump, which
+// `serde_json::Map` would sort by name
+struct JsonRecord<'a> {
+  columns: &'a [DumpColumn],
+  values: Vec<Value>,
+}
+
+impl Serialize for JsonRecord<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(self.values.len()))?;
+    for (column, value) in self.columns.iter().zip(self.values.iter()) {
+      map.serialize_entry(&column.name, value)?;
+    }
+    map.end()
+  }
+}
+
+fn write_table(table: &DumpTable, format: ConvertFormat, path: &Path) -> Result<(), String> {
+  let file = File::create(path).map_err(|e| e.to_string())?;
+  let records: Vec<Vec<Value>> = table
+    .rows
+    .iter()
+    .map(|row| {
+      table
+        .columns
+        .iter()
+        .zip(row.iter())
+        .map(|(c, v)| typed_value(&c.data_type, v))
+        .collect()
+    })
+    .collect();
+  let as_object = |values: Vec<Value>| JsonRecord {
+    columns: &table.columns,
+    values,
+  };
+  match format {
+    ConvertFormat::Json => {
+      let objects: Vec<JsonRecord> = records.into_iter().map(as_object).collect();
+      serde_json::to_writer_pretty(BufWriter::new(file), &objects).map_err(|e| e.to_string())
+    }
+    ConvertFormat::Ndjson => {
+      let mut writer = BufWriter::new(file);
+      for record in records.into_iter() {
+        let line = serde_json::to_string(&as_object(record)).map_err(|e| e.to_string())?;
+        writeln!(writer, "{}", line).map_err(|e| e.to_string())?;
+      }
+      writer.flush().map_err(|e| e.to_string())
+    }
+    ConvertFormat::Csv => {
+      let mut writer = csv::Writer::from_writer(file);
+      let header: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+      writer.write_record(&header).map_err(|e| e.to_string())?;
+      for record in records.iter() {
+        let fields: Vec<String> = record.iter().map(csv_value).collect();
+        writer.write_record(&fields).map_err(|e| e.to_string())?;
+      }
+      writer.flush().map_err(|e| e.to_string())
+    }
+  }
+}
+
+// Writes every table found in a dump generated by `export` to its own file
+pub fn convert(dump_file: &str, format: ConvertFormat, out_dir: &str) {
+  let dump = Dump::from_file(dump_file);
+  if let Err(e) = create_dir_all(out_dir) {
+    eprintln!(
+      "{} {} {} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Failed to create output directory"),
+      dlg::divider(),
+      dlg::red(out_dir),
+      dlg::divider(),
+      e
+    );
+    exit(1);
+  }
+  for table in dump.tables.values() {
+    let file_name = format!("{}.{}", table.name.replace('/', "_"), format.extension());
+    let path = Path::new(out_dir).join(file_name);
+    if let Err(e) = write_table(table, format, &path) {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Failed to convert table"),
+        dlg::divider(),
+        dlg::red(&table.name),
+        dlg::divider(),
+        e
+      );
+      exit(1);
+    }
+  }
+  let count = dump.tables.len();
+  let table_text = if count == 1 { "table" } else { "tables" };
+  println!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold(&format!("Converted {} {} into", count, table_text)),
+    dlg::divider(),
+    dlg::green(out_dir),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn values(dump: &Dump, table: &str) -> Vec<Vec<Option<String>>> {
+    dump.tables[table].rows.clone()
+  }
+
+  fn text(value: &str) -> Option<String> {
+    Some(value.to_string())
+  }
+
+  #[test]
+  fn parses_quoted_and_escaped_strings() {
+    let dump = Dump::parse(
+      "INSERT INTO public.\"order\" (id, note, path) VALUES (1, 'it''s; done', E'a\\\\b\\nc');\n",
+    )
+    .unwrap();
+    assert_eq!(
+      values(&dump, "order"),
+      vec![vec![text("1"), text("it's; done"), text("a\\b\nc")]]
+    );
+  }
+
+  #[test]
+  fn parses_nulls() {
+    let sql = "INSERT INTO t (id, name) VALUES (1, NULL), (2, 'NULL');\n\
+               COPY public.u (id, name) FROM stdin;\n\
+               1\t\\N\n\
+               \\.\n";
+    let dump = Dump::parse(sql).unwrap();
+    assert_eq!(
+      values(&dump, "t"),
+      vec![vec![text("1"), None], vec![text("2"), text("NULL")]]
+    );
+    assert_eq!(values(&dump, "u"), vec![vec![text("1"), None]]);
+  }
+
+  #[test]
+  fn parses_multi_row_inserts_across_lines() {
+    let sql = "INSERT INTO t (id, name, tags) VALUES\n\
+               (1, 'a, (b)', '{x,y}'),\n\
+               (2, 'c', NULL::text[]);\n";
+    let dump = Dump::parse(sql).unwrap();
+    assert_eq!(
+      values(&dump, "t"),
+      vec![
+        vec![text("1"), text("a, (b)"), text("{x,y}")],
+        vec![text("2"), text("c"), None],
+      ]
+    );
+  }
+
+  #[test]
+  fn parses_copy_escapes() {
+    let sql = "COPY t (id, note) FROM stdin;\n1\ttab\\there\\\\\n\\.\n";
+    let dump = Dump::parse(sql).unwrap();
+    assert_eq!(
+      values(&dump, "t"),
+      vec![vec![text("1"), text("tab\there\\")]]
+    );
+  }
+
+  #[test]
+  fn parses_columns_and_primary_key() {
+    let sql = "CREATE TABLE public.t (\n\
+               \x20 name character varying NOT NULL,\n\
+               \x20 size integer DEFAULT 1\n\
+               );\n\
+               ALTER TABLE ONLY public.t ADD CONSTRAINT t_pkey PRIMARY KEY (name);\n";
+    let dump = Dump::parse(sql).unwrap();
+    let table = &dump.tables["t"];
+    let types: Vec<&str> = table.columns.iter().map(|c| c.data_type.as_str()).collect();
+    assert_eq!(types, vec!["character varying", "integer"]);
+    assert_eq!(table.key_columns(), vec!["name".to_string()]);
+  }
+
+  // Audit trigger function as found in full exports
+  const AUDIT_FUNCTION: &str = "CREATE FUNCTION public.iasql_audit() RETURNS trigger\n\
+    \x20   LANGUAGE plpgsql\n\
+    \x20   AS $$\n\
+    BEGIN\n\
+    \x20 -- don't touch, the engine reads this table\n\
+    \x20 INSERT INTO iasql_audit_log (ts, change_type, table_name, new) VALUES (now(), TG_OP, TG_TABLE_NAME, 'x;y');\n\
+    \x20 RETURN NEW;\n\
+    END;\n\
+    $$;\n";
+
+  #[test]
+  fn skips_dollar_quoted_function_bodies() {
+    let sql = format!(
+      "{}\nINSERT INTO iasql_audit_log (id, change_type) VALUES (1, 'INSERT');\n\
+       INSERT INTO t (id, name) VALUES (1, $tag$it's$tag$);\n",
+      AUDIT_FUNCTION
+    );
+    let dump = Dump::parse(&sql).unwrap();
+    assert_eq!(
+      values(&dump, "iasql_audit_log"),
+      vec![vec![text("1"), text("INSERT")]]
+    );
+    assert_eq!(dump.tables["t"].rows.len(), 1);
+  }
+
+  #[test]
+  fn parses_backslash_escaped_quotes() {
+    let dump = Dump::parse("INSERT INTO t (id, note) VALUES (1, E'it\\'s; done');\n").unwrap();
+    assert_eq!(
+      values(&dump, "t"),
+      vec![vec![text("1"), text("it's; done")]]
+    );
+  }
+
+  #[test]
+  fn maps_values_outside_function_bodies_only() {
+    let sql = format!(
+      "{}INSERT INTO t (id, name) VALUES (1, 'x;y'), (2, E'a\\'b');\n",
+      AUDIT_FUNCTION
+    );
+    let mapped = map_values(&sql, |value| value.to_uppercase());
+    assert_eq!(
+      mapped,
+      format!(
+        "{}INSERT INTO t (id, name) VALUES (1, 'X;Y'), (2, E'A''B');\n",
+        AUDIT_FUNCTION
+      )
+    );
+  }
+
+  #[test]
+  fn rejects_unbalanced_inserts() {
+    assert!(Dump::parse("INSERT INTO t (id) VALUES ((1);\n").is_err());
+    assert!(Dump::parse("INSERT INTO t (id, name) VALUES (1);\n").is_err());
+  }
+
+  #[test]
+  fn diffs_records_by_key() {
+    let old = Dump::parse("INSERT INTO t (id, name) VALUES (1, 'a'), (2, 'b');\n").unwrap();
+    let new = Dump::parse("INSERT INTO t (id, name) VALUES (2, 'c'), (3, 'd');\n").unwrap();
+    let diff = diff_table(old.tables.get("t"), new.tables.get("t"));
+    assert_eq!(diff.added, vec![vec!["3".to_string(), "d".to_string()]]);
+    assert_eq!(diff.removed, vec![vec!["1".to_string(), "a".to_string()]]);
+    assert_eq!(diff.changed.len(), 1);
+    let (key, changes) = &diff.changed[0];
+    assert_eq!(key, "id=2");
+    assert_eq!(changes.len(), 1);
+    assert_eq!(
+      (
+        changes[0].column.as_str(),
+        changes[0].before.as_str(),
+        changes[0].after.as_str()
+      ),
+      ("name", "b", "c")
+    );
+  }
+
+  #[test]
+  fn diffs_missing_tables() {
+    let new = Dump::parse("INSERT INTO t (id) VALUES (1);\n").unwrap();
+    let diff = diff_table(None, new.tables.get("t"));
+    assert_eq!(diff.table, "t");
+    assert_eq!(diff.added.len(), 1);
+    assert!(diff.removed.is_empty() && diff.changed.is_empty());
+  }
+
+  fn converted(format: ConvertFormat) -> String {
+    let sql = "CREATE TABLE t (\n\
+               \x20 zone text,\n\
+               \x20 id integer,\n\
+               \x20 enabled boolean,\n\
+               \x20 ratio numeric,\n\
+               \x20 tags text[],\n\
+               \x20 meta jsonb,\n\
+               \x20 note text\n\
+               );\n\
+               INSERT INTO t VALUES ('a', 1, 't', 0.5, '{x,\"y z\",NULL}', '{\"k\": [1]}', NULL);\n";
+    let dump = Dump::parse(sql).unwrap();
+    let path = std::env::temp_dir().join(format!(
+      "iasql-convert-{}.{}",
+      std::process::id(),
+      format.extension()
+    ));
+    write_table(&dump.tables["t"], format, &path).unwrap();
+    let out = read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    out
+  }
+
+  #[test]
+  fn converts_to_typed_json_in_column_order() {
+    let out = converted(ConvertFormat::Ndjson);
+    assert_eq!(
+      out.trim(),
+      r#"{"zone":"a","id":1,"enabled":true,"ratio":0.5,"tags":["x","y z",null],"meta":{"k":[1]},"note":null}"#
+    );
+    let records: Vec<Value> = serde_json::from_str(&converted(ConvertFormat::Json)).unwrap();
+    assert_eq!(
+      records,
+      vec![serde_json::from_str::<Value>(out.trim()).unwrap()]
+    );
+  }
+
+  #[test]
+  fn converts_to_csv() {
+    let out = converted(ConvertFormat::Csv);
+    let mut reader = csv::Reader::from_reader(out.as_bytes());
+    let header: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+    assert_eq!(
+      header,
+      vec!["zone", "id", "enabled", "ratio", "tags", "meta", "note"]
+    );
+    let record: Vec<String> = reader
+      .records()
+      .next()
+      .unwrap()
+      .unwrap()
+      .iter()
+      .map(String::from)
+      .collect();
+    assert_eq!(
+      record,
+      vec![
+        "a",
+        "1",
+        "true",
+        "0.5",
+        r#"["x","y z",null]"#,
+        r#"{"k":[1]}"#,
+        ""
+      ]
+    );
+  }
+
+  #[test]
+  fn merges_records_on_unique_keys() {
+    let sql = "INSERT INTO vpc (id, name, cidr) VALUES (7, 'main', '10.0.0.0/16');\n\
+               INSERT INTO subnet (id, name, vpc_id) VALUES (3, 'a', 7), (4, 'b', NULL);\n\
+               INSERT INTO iasql_module (name) VALUES ('aws_vpc');\n";
+    let dump = Dump::parse(sql).unwrap();
+    let mut keys = TableKeys::default();
+    keys
+      .unique
+      .insert("vpc".to_string(), vec![vec!["name".to_string()]]);
+    keys.unique.insert(
+      "subnet".to_string(),
+      vec![vec!["name".to_string(), "vpc_id".to_string()]],
+    );
+    keys
+      .foreign
+      .entry("subnet".to_string())
+      .or_default()
+      .insert("vpc_id".to_string(), ("vpc".to_string(), "id".to_string()));
+    let merged = dump.merge_sql("iasql_", &keys).unwrap();
+    let statements: Vec<&str> = merged.lines().collect();
+    assert_eq!(
+      statements,
+      vec![
+        "INSERT INTO \"vpc\" (\"name\", \"cidr\") VALUES ('main', '10.0.0.0/16') \
+         ON CONFLICT (\"name\") DO UPDATE SET \"cidr\" = EXCLUDED.\"cidr\";",
+        "INSERT INTO \"subnet\" (\"name\", \"vpc_id\") VALUES ('a', \
+         (SELECT \"id\" FROM \"vpc\" WHERE \"name\" = 'main')) ON CONFLICT DO NOTHING;",
+        "INSERT INTO \"subnet\" (\"name\", \"vpc_id\") VALUES ('b', NULL) ON CONFLICT DO NOTHING;",
+      ]
+    );
+  }
+
+  #[test]
+  fn refuses_to_merge_without_unique_key() {
+    let dump = Dump::parse("INSERT INTO t (id, name) VALUES (1, 'a');\n").unwrap();
+    assert!(dump.merge_sql("iasql_", &TableKeys::default()).is_err());
+  }
+}
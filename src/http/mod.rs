@@ -30,14 +30,18 @@ pub static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
 
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-fn get_url() -> &'static str {
+// Environment of the engine, set with IASQL_ENV
+pub fn get_env() -> String {
   let default = if cfg!(debug_assertions) {
     "local"
   } else {
     "production"
   };
-  let env = std::env::var("IASQL_ENV").unwrap_or(default.to_string());
-  match env.as_str() {
+  std::env::var("IASQL_ENV").unwrap_or(default.to_string())
+}
+
+fn get_url() -> &'static str {
+  match get_env().as_str() {
     "local" => "http://localhost:8088",
     "staging" => "https://api-staging.iasql.com",
     _ => "https://api.iasql.com",
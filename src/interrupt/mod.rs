@@ -0,0 +1,48 @@
+use dialoguer::console::Term;
+use once_cell::sync::Lazy;
+
+use std::process::exit;
+use std::sync::Mutex;
+use tokio::signal::ctrl_c;
+
+use crate::dialoguer as dlg;
+
+// Conventional exit code of processes interrupted with Ctrl-C
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// What to tell the user if interrupted now, e.g. that an operation may still be running
+// in the engine
+static NOTE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn set_note(note: Option<String>) {
+  *NOTE.lock().unwrap() = note;
+}
+
+// Spinners and prompts hide the cursor while drawing
+fn restore_terminal() {
+  for term in [Term::stdout(), Term::stderr()] {
+    if term.is_term() {
+      let _ = term.show_cursor();
+    }
+  }
+}
+
+pub fn exit_interrupted() -> ! {
+  restore_terminal();
+  eprintln!();
+  match NOTE.lock().unwrap().as_ref() {
+    Some(note) => eprintln!("{} {}", dlg::warn_prefix(), note),
+    None => eprintln!("{} {}", dlg::warn_prefix(), dlg::bold("Cancelled")),
+  }
+  exit(INTERRUPTED_EXIT_CODE);
+}
+
+// Handles Ctrl-C for the whole process. Prompts reading single keys get it as an error
+// instead and exit the same way.
+pub fn listen() {
+  tokio::spawn(async {
+    if ctrl_c().await.is_ok() {
+      exit_interrupted();
+    }
+  });
+}
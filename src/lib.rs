@@ -3,4 +3,13 @@
 pub mod http;
 pub mod api;
 pub mod auth;
+pub mod batch;
+pub mod config;
 pub mod dialoguer;
+pub mod dump;
+pub mod interrupt;
+pub mod lockfile;
+pub mod manifest;
+pub mod policy;
+pub mod state;
+pub mod template;
@@ -0,0 +1,228 @@
+use std::fs::{read_to_string, write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::module::{list_mods, split_module_name, Module};
+use crate::dialoguer as dlg;
+
+// Lockfile used when none is given with `--file`
+const DEFAULT_LOCK_FILE: &str = "iasql.lock";
+const LOCK_FILE_HEADER: &str =
+  "# Generated by `iasql mods lock`. Check it with `iasql mods verify`.\n\n";
+
+// The modules installed in a db, pinned to exact versions:
+//
+// db = "prod"
+//
+// [[module]]
+// name = "aws_ec2"
+// version = "0.0.1"
+// dependencies = ["aws_account@0.0.1"]
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Lockfile {
+  // Db the lockfile was written from
+  db: String,
+  #[serde(default)]
+  module: Vec<LockedModule>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct LockedModule {
+  name: String,
+  version: String,
+  #[serde(default)]
+  dependencies: Vec<String>,
+}
+
+impl LockedModule {
+  fn from_module(m: &Module) -> LockedModule {
+    let (name, version) = split_module_name(&m.name);
+    let mut dependencies = m.dependencies.clone();
+    dependencies.sort();
+    LockedModule {
+      name: name.to_string(),
+      version: version.to_string(),
+      dependencies,
+    }
+  }
+
+  fn reference(&self) -> String {
+    format!("{}@{}", self.name, self.version)
+  }
+}
+
+async fn installed(db: &str) -> Vec<LockedModule> {
+  let mut mods: Vec<LockedModule> = list_mods(Some(db))
+    .await
+    .iter()
+    .map(LockedModule::from_module)
+    .collect();
+  mods.sort_by(|a, b| a.name.cmp(&b.name));
+  mods
+}
+
+// Differences of the modules installed in a db from the locked ones
+fn differences(locked: &[LockedModule], installed: &[LockedModule]) -> Vec<(String, String)> {
+  let mut diffs = vec![];
+  for l in locked.iter() {
+    match installed.iter().find(|i| i.name == l.name) {
+      None => diffs.push((l.reference(), "locked but not installed".to_string())),
+      Some(i) if i.version != l.version => diffs.push((
+        l.name.clone(),
+        format!("version {} installed, {} locked", i.version, l.version),
+      )),
+      Some(i) if i.dependencies != l.dependencies => diffs.push((
+        l.reference(),
+        format!(
+          "depends on {}, locked with {}",
+          i.dependencies.join(", "),
+          l.dependencies.join(", ")
+        ),
+      )),
+      Some(_) => {}
+    }
+  }
+  for i in installed.iter() {
+    if !locked.iter().any(|l| l.name == i.name) {
+      diffs.push((i.reference(), "installed but not locked".to_string()));
+    }
+  }
+  diffs
+}
+
+// Writes the modules installed in the db to the lockfile
+pub async fn lock(db: &str, lock_file: Option<&str>) {
+  let lock_file = lock_file.unwrap_or(DEFAULT_LOCK_FILE);
+  let lockfile = Lockfile {
+    db: db.to_string(),
+    module: installed(db).await,
+  };
+  let result = toml::to_string(&lockfile)
+    .map_err(|e| e.to_string())
+    .and_then(|content| {
+      write(lock_file, format!("{}{}", LOCK_FILE_HEADER, content)).map_err(|e| e.to_string())
+    });
+  if let Err(e) = result {
+    dlg::exit_with_error(&format!("Failed to write lockfile {}", lock_file), &e);
+  }
+  println!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold(&format!("Locked {} modules in", lockfile.module.len())),
+    dlg::divider(),
+    dlg::green(lock_file),
+  );
+}
+
+// Exits with 1 if the modules installed in the db are not the locked ones
+pub async fn verify(db: &str, lock_file: Option<&str>) {
+  let lock_file = lock_file.unwrap_or(DEFAULT_LOCK_FILE);
+  let lockfile: Lockfile = match read_to_string(lock_file)
+    .map_err(|e| e.to_string())
+    .and_then(|l| toml::from_str(&l).map_err(|e| e.to_string()))
+  {
+    Ok(lockfile) => lockfile,
+    Err(e) => dlg::exit_with_error(&format!("Failed to read lockfile {}", lock_file), &e),
+  };
+  let diffs = differences(&lockfile.module, &installed(db).await);
+  if diffs.is_empty() {
+    println!(
+      "{} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold(&format!("Modules installed in {} match", db)),
+      dlg::divider(),
+      dlg::green(lock_file),
+    );
+    return;
+  }
+  for (module, detail) in diffs.iter() {
+    eprintln!(
+      "{} {} {} {} {} {}",
+      dlg::err_prefix(),
+      dlg::bold("Module drift"),
+      dlg::divider(),
+      dlg::red(module),
+      dlg::divider(),
+      detail,
+    );
+  }
+  dlg::exit_with_error(
+    &format!(
+      "Modules installed in {} differ from the lockfile in {} modules",
+      db,
+      diffs.len()
+    ),
+    lock_file,
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn locked(name: &str, version: &str, dependencies: &[&str]) -> LockedModule {
+    LockedModule {
+      name: name.to_string(),
+      version: version.to_string(),
+      dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+    }
+  }
+
+  #[test]
+  fn matches_identical_modules() {
+    let mods = vec![locked("aws_account", "0.0.1", &[])];
+    assert!(differences(&mods, &[locked("aws_account", "0.0.1", &[])]).is_empty());
+  }
+
+  #[test]
+  fn reports_added_and_removed_modules() {
+    let lock = vec![
+      locked("aws_account", "0.0.1", &[]),
+      locked("aws_rds", "0.0.1", &["aws_account@0.0.1"]),
+    ];
+    let installed = vec![
+      locked("aws_account", "0.0.1", &[]),
+      locked("aws_ec2", "0.0.1", &["aws_account@0.0.1"]),
+    ];
+    assert_eq!(
+      differences(&lock, &installed),
+      vec![
+        (
+          "aws_rds@0.0.1".to_string(),
+          "locked but not installed".to_string()
+        ),
+        (
+          "aws_ec2@0.0.1".to_string(),
+          "installed but not locked".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn reports_changed_versions_and_dependencies() {
+    let lock = vec![
+      locked("aws_account", "0.0.1", &[]),
+      locked("aws_ec2", "0.0.1", &["aws_account@0.0.1"]),
+    ];
+    let installed = vec![
+      locked("aws_account", "0.0.2", &[]),
+      locked("aws_ec2", "0.0.1", &["aws_account@0.0.2"]),
+    ];
+    assert_eq!(
+      differences(&lock, &installed),
+      vec![
+        (
+          "aws_account".to_string(),
+          "version 0.0.2 installed, 0.0.1 locked".to_string()
+        ),
+        (
+          "aws_ec2@0.0.1".to_string(),
+          "depends on aws_account@0.0.2, locked with aws_account@0.0.1".to_string()
+        ),
+      ]
+    );
+  }
+}
@@ -1,14 +1,27 @@
 use std::env;
+use std::process::exit;
 
-use clap::{crate_description, crate_name, crate_version, App, AppSettings, Arg, SubCommand};
+use clap::{
+  crate_description, crate_name, crate_version, App, AppSettings, Arg, ArgMatches, SubCommand,
+};
 
-use iasql::api::{db, module};
+use iasql::api::{db, job, module};
 use iasql::auth;
+use iasql::batch;
+use iasql::config::{Config, Limits};
+use iasql::dialoguer as dlg;
+use iasql::dump;
+use iasql::interrupt;
+use iasql::lockfile;
+use iasql::manifest;
+use iasql::policy::PolicyCheck;
+use iasql::template;
 
 extern crate iasql;
 
 #[tokio::main]
 pub async fn main() {
+  interrupt::listen();
   let app = App::new(crate_name!())
     .version(crate_version!())
     .about(crate_description!())
@@ -30,6 +43,7 @@ pub async fn main() {
         .arg(Arg::from_usage("[db]"))
         .arg(Arg::from_usage("[dump_file]"))
         .arg(Arg::from_usage("--data-only"))
+        .args(&batch_args())
         .arg(Arg::from_usage("--noninteractive")),
       SubCommand::with_name("disconnect")
         .alias("remove")
@@ -42,21 +56,79 @@ pub async fn main() {
         .display_order(4)
         .about("Create, delete or update the cloud resources in a hosted db")
         .arg(Arg::from_usage("[db]"))
+        .arg(Arg::from_usage("--plan=[PLAN_FILE]"))
+        .arg(Arg::from_usage("--policy=[POLICY_FILE]"))
+        .arg(Arg::from_usage("--allow-policy-violations"))
+        .arg(
+          Arg::with_name("max-deletes")
+            .long("max-deletes")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("[TABLE=]MAX"),
+        )
+        .arg(
+          Arg::with_name("max-replaces")
+            .long("max-replaces")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("[TABLE=]MAX"),
+        )
+        .arg(Arg::from_usage("--verify"))
+        .arg(Arg::from_usage("--max-iterations=[N]"))
+        .args(&plan_filter_args())
+        .args(&batch_args())
         .arg(Arg::from_usage("--noninteractive")),
       SubCommand::with_name("plan")
         .display_order(5)
         .about("Display a preview of the resources in a db to be modified on the next `apply`")
         .arg(Arg::from_usage("[db]"))
+        .arg(
+          Arg::from_usage("--output=[OUTPUT]")
+            .alias("format")
+            .possible_values(&["text", "json", "markdown", "html"])
+            .default_value("text"),
+        )
+        .arg(Arg::from_usage("--detailed-exitcode"))
+        .arg(Arg::from_usage("--out=[PLAN_FILE]"))
+        .arg(Arg::from_usage("--check"))
+        .arg(Arg::from_usage("--policy=[POLICY_FILE]"))
+        .arg(Arg::from_usage("--allow-policy-violations"))
+        .args(&plan_filter_args())
+        .args(&batch_args())
+        .arg(Arg::from_usage("--noninteractive")),
+      SubCommand::with_name("drift")
+        .display_order(18)
+        .about("Check whether the cloud account drifted from the resources declared in a hosted db")
+        .arg(Arg::from_usage("[db]"))
+        .arg(
+          Arg::from_usage("--output=[OUTPUT]")
+            .alias("format")
+            .possible_values(&["text", "json", "markdown", "html"])
+            .default_value("text"),
+        )
+        .arg(Arg::from_usage("--watch"))
+        .arg(Arg::from_usage("--interval=[INTERVAL]").default_value("10m"))
         .arg(Arg::from_usage("--noninteractive")),
       SubCommand::with_name("sync")
         .display_order(6)
         .about("Synchronize a hosted db with the current state of the cloud account")
         .arg(Arg::from_usage("[db]"))
+        .arg(Arg::from_usage("--dry-run"))
+        .args(&plan_filter_args())
+        .args(&batch_args())
         .arg(Arg::from_usage("--noninteractive")),
       SubCommand::with_name("install")
         .display_order(7)
         .about("Install mods in a given hosted db")
-        .arg(Arg::from_usage("--db=[DB]"))
+        .arg(
+          Arg::from_usage("--db=[DB]...")
+            .number_of_values(1)
+            .use_delimiter(true),
+        )
+        .arg(Arg::from_usage("--all"))
+        .arg(Arg::from_usage("--parallel=[N]").default_value("4"))
         .arg(Arg::with_name("modules").min_values(1))
         .arg(Arg::from_usage("--noninteractive")),
       SubCommand::with_name("uninstall")
@@ -78,7 +150,125 @@ pub async fn main() {
         .display_order(9)
         .alias("modules")
         .about("List all modules or list the modules installed in a given hosted db")
+        .arg(Arg::from_usage(
+          "[db] 'Hosted db, also given with --db when named like a subcommand'",
+        ))
+        .args(&batch_args())
+        .arg(Arg::from_usage("--noninteractive"))
+        .subcommand(
+          SubCommand::with_name("lock")
+            .about("Pin the modules installed in a hosted db in a lockfile")
+            .arg(Arg::from_usage("[db]"))
+            .arg(Arg::from_usage(
+              "--file=[FILE] 'Lockfile, iasql.lock by default'",
+            ))
+            .arg(Arg::from_usage("--noninteractive")),
+        )
+        .subcommand(
+          SubCommand::with_name("verify")
+            .about("Check the modules installed in a hosted db match a lockfile")
+            .arg(Arg::from_usage("[db]"))
+            .arg(Arg::from_usage(
+              "--file=[FILE] 'Lockfile, iasql.lock by default'",
+            ))
+            .arg(Arg::from_usage("--noninteractive")),
+        ),
+      SubCommand::with_name("clone")
+        .display_order(14)
+        .about("Copy the state of a hosted db into a new hosted db")
+        .arg(Arg::from_usage("[src_db]"))
+        .arg(Arg::from_usage("[dst_db]"))
+        .arg(Arg::from_usage("--aws-region=[REGION]"))
+        .arg(Arg::from_usage("--aws-profile=[PROFILE]"))
+        .arg(Arg::from_usage("--noninteractive")),
+      SubCommand::with_name("promote")
+        .display_order(15)
+        .about("Promote the infrastructure declared in a hosted db into another hosted db")
+        .arg(Arg::from_usage("[src_db]"))
+        .arg(Arg::from_usage("[dst_db]"))
+        .arg(Arg::from_usage("--mapping=[FILE]"))
+        .arg(Arg::from_usage(
+          "--create 'Create the target db from a full export of the source db if it does not exist'",
+        ))
+        .arg(Arg::from_usage("--noninteractive")),
+      SubCommand::with_name("template")
+        .display_order(17)
+        .about("Create reusable dump templates and render them into dump files")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("create")
+            .about("Export a hosted db replacing the value of each variable with a placeholder")
+            .arg(Arg::from_usage("[db]"))
+            .arg(Arg::from_usage("[template_file]"))
+            .arg(Arg::from_usage("--vars=<VARS>").use_delimiter(true))
+            .arg(Arg::from_usage("--set=[VAR=VALUE]...").number_of_values(1))
+            .arg(Arg::from_usage("--noninteractive")),
+        )
+        .subcommand(
+          SubCommand::with_name("render")
+            .about("Replace the placeholders in a template to produce a dump file")
+            .arg(Arg::from_usage("<template_file>"))
+            .arg(Arg::from_usage("[dump_file]"))
+            .arg(Arg::from_usage("--set=[VAR=VALUE]...").number_of_values(1)),
+        ),
+      SubCommand::with_name("dump")
+        .display_order(13)
+        .about("Inspect dump files generated by `export` without a hosted db")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("diff")
+            .about("Display the records added, removed or changed between two dump files")
+            .arg(Arg::from_usage("<old_dump>"))
+            .arg(Arg::from_usage("<new_dump>")),
+        )
+        .subcommand(
+          SubCommand::with_name("convert")
+            .about("Convert the records in a dump file into one file per table")
+            .arg(Arg::from_usage("<dump_file>"))
+            .arg(
+              Arg::from_usage("--format=[FORMAT]")
+                .possible_values(&["json", "csv", "ndjson"])
+                .default_value("json"),
+            )
+            .arg(Arg::from_usage("--out-dir=[DIR]").default_value(".")),
+        ),
+      SubCommand::with_name("jobs")
+        .display_order(19)
+        .about("Follow the long-running operations started in the hosted engine")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("list")
+            .about("List the jobs started in the hosted engine")
+            .arg(Arg::from_usage("--db=[DB]"))
+            .arg(Arg::from_usage("--noninteractive")),
+        )
+        .subcommand(
+          SubCommand::with_name("show")
+            .about("Display the status of a job")
+            .arg(Arg::from_usage("<id>"))
+            .arg(Arg::from_usage("--noninteractive")),
+        )
+        .subcommand(
+          SubCommand::with_name("wait")
+            .about("Wait for a job to finish")
+            .arg(Arg::from_usage("<id>"))
+            .arg(Arg::from_usage("--noninteractive")),
+        ),
+      SubCommand::with_name("use")
+        .display_order(20)
+        .about("Set the db commands default to for the current environment")
         .arg(Arg::from_usage("[db]"))
+        .arg(Arg::from_usage("--unset 'Stop defaulting to a db'").conflicts_with("db"))
+        .arg(Arg::from_usage("--noninteractive")),
+      SubCommand::with_name("up")
+        .display_order(21)
+        .about("Provision the hosted db of a project file and install the modules it declares")
+        .arg(Arg::from_usage(
+          "--file=[FILE] 'Project file, iasql.toml by default'",
+        ))
+        .arg(Arg::from_usage(
+          "--create 'Provision the db if it does not exist'",
+        ))
         .arg(Arg::from_usage("--noninteractive")),
     ]);
 
@@ -94,17 +284,18 @@ pub async fn main() {
     ("connect", Some(s_matches)) => {
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
-      let db_name = db::new(s_matches.value_of("db"), noninteractive).await;
+      let db_name = db::new(s_matches.value_of("db"), None, noninteractive).await;
       if !noninteractive {
         let modules = module::mods_to_install(&db_name, None).await;
         module::install(&db_name, modules, noninteractive).await;
       }
     }
     ("export", Some(s_matches)) => {
+      maybe_run_batch("export", s_matches, "dbs").await;
       let noninteractive = s_matches.is_present("noninteractive");
       let data_only = s_matches.is_present("data-only");
       auth::login(false, noninteractive).await;
-      let db = db::get_or_select_db(s_matches.value_of("db")).await;
+      let db = db::get_or_select_db(s_matches.value_of("db").or(s_matches.value_of("dbs"))).await;
       let dump_file = db::get_or_input_arg(s_matches.value_of("dump_file"), "Dump file");
       db::export(&db, dump_file, data_only).await;
     }
@@ -115,34 +306,132 @@ pub async fn main() {
       db::remove(&db, noninteractive).await;
     }
     ("apply", Some(s_matches)) => {
+      maybe_run_batch("apply", s_matches, "dbs").await;
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
-      let db = db::get_or_select_db(s_matches.value_of("db")).await;
-      db::apply(&db, noninteractive).await;
+      let db = db::get_or_select_db(s_matches.value_of("db").or(s_matches.value_of("dbs"))).await;
+      let filter = plan_filter(s_matches);
+      let policy = PolicyCheck::load(
+        s_matches.value_of("policy"),
+        s_matches.is_present("allow-policy-violations"),
+      );
+      let limits = Config::load().limits(&db).merge(&apply_limits(s_matches));
+      // a maximum number of iterations implies verifying
+      let verify = match s_matches.value_of("max-iterations") {
+        Some(n) => match n.parse::<usize>() {
+          Ok(n) if n > 0 => Some(n),
+          _ => {
+            eprintln!(
+              "{} {} {} {}",
+              dlg::err_prefix(),
+              dlg::bold("Invalid value for --max-iterations"),
+              dlg::divider(),
+              dlg::red(n),
+            );
+            exit(1);
+          }
+        },
+        None if s_matches.is_present("verify") => Some(1),
+        None => None,
+      };
+      db::apply(
+        &db,
+        noninteractive,
+        s_matches.value_of("plan"),
+        &filter,
+        policy.as_ref(),
+        &limits,
+        verify,
+      )
+      .await;
     }
     ("plan", Some(s_matches)) => {
+      maybe_run_batch("plan", s_matches, "dbs").await;
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
-      let db = db::get_or_select_db(s_matches.value_of("db")).await;
-      db::plan(&db, noninteractive).await;
+      let db = db::get_or_select_db(s_matches.value_of("db").or(s_matches.value_of("dbs"))).await;
+      let output = db::PlanOutput::parse(s_matches.value_of("output").unwrap()).unwrap();
+      let detailed_exitcode = s_matches.is_present("detailed-exitcode");
+      let plan_file = s_matches.value_of("out");
+      let filter = plan_filter(s_matches);
+      let policy = if s_matches.is_present("check") {
+        Some(PolicyCheck::load_required(
+          s_matches.value_of("policy"),
+          s_matches.is_present("allow-policy-violations"),
+        ))
+      } else {
+        None
+      };
+      db::plan(
+        &db,
+        noninteractive,
+        output,
+        detailed_exitcode,
+        plan_file,
+        &filter,
+        policy.as_ref(),
+      )
+      .await;
     }
-    ("sync", Some(s_matches)) => {
+    ("drift", Some(s_matches)) => {
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
       let db = db::get_or_select_db(s_matches.value_of("db")).await;
-      db::sync(&db, noninteractive).await;
+      let output = db::PlanOutput::parse(s_matches.value_of("output").unwrap()).unwrap();
+      let watch = if s_matches.is_present("watch") {
+        let interval = s_matches.value_of("interval").unwrap();
+        match db::parse_interval(interval) {
+          Some(interval) => Some(interval),
+          None => {
+            eprintln!(
+              "{} {} {} {}",
+              dlg::err_prefix(),
+              dlg::bold("Invalid interval"),
+              dlg::divider(),
+              dlg::red(interval),
+            );
+            exit(1);
+          }
+        }
+      } else {
+        None
+      };
+      db::drift(&db, output, watch).await;
     }
-    ("dbs", Some(s_matches)) => {
+    ("sync", Some(s_matches)) => {
+      maybe_run_batch("sync", s_matches, "dbs").await;
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
-      db::list().await;
+      let db = db::get_or_select_db(s_matches.value_of("db").or(s_matches.value_of("dbs"))).await;
+      let filter = plan_filter(s_matches);
+      let dry_run = s_matches.is_present("dry-run");
+      db::sync(&db, noninteractive, dry_run, &filter).await;
     }
-    ("mods", Some(s_matches)) => {
+    ("dbs", Some(s_matches)) => {
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
-      module::list(s_matches.value_of("db")).await;
+      db::list().await;
     }
+    ("mods", Some(s_matches)) => match s_matches.subcommand() {
+      ("lock", Some(l_matches)) => {
+        auth::login(false, l_matches.is_present("noninteractive")).await;
+        let db = db::get_or_select_db(l_matches.value_of("db")).await;
+        lockfile::lock(&db, l_matches.value_of("file")).await;
+      }
+      ("verify", Some(l_matches)) => {
+        auth::login(false, l_matches.is_present("noninteractive")).await;
+        let db = db::get_or_select_db(l_matches.value_of("db")).await;
+        lockfile::verify(&db, l_matches.value_of("file")).await;
+      }
+      _ => {
+        maybe_run_batch("mods", s_matches, "dbs").await;
+        let noninteractive = s_matches.is_present("noninteractive");
+        auth::login(false, noninteractive).await;
+        module::list(s_matches.value_of("db").or(s_matches.value_of("dbs"))).await;
+      }
+    },
     ("install", Some(s_matches)) => {
+      maybe_run_batch("install", s_matches, "db").await;
       let noninteractive = s_matches.is_present("noninteractive");
       auth::login(false, noninteractive).await;
       let db = db::get_or_select_db(s_matches.value_of("db")).await;
@@ -156,7 +445,221 @@ pub async fn main() {
       let modules = module::mods_to_remove(&db, s_matches.values_of_lossy("modules")).await;
       module::uninstall(&db, modules, noninteractive).await;
     }
+    ("clone", Some(s_matches)) => {
+      let noninteractive = s_matches.is_present("noninteractive");
+      auth::login(false, noninteractive).await;
+      let src_db = db::get_or_select_db(s_matches.value_of("src_db")).await;
+      db::clone(
+        &src_db,
+        s_matches.value_of("dst_db"),
+        s_matches.value_of("aws-region"),
+        s_matches.value_of("aws-profile"),
+        noninteractive,
+      )
+      .await;
+    }
+    ("promote", Some(s_matches)) => {
+      let noninteractive = s_matches.is_present("noninteractive");
+      auth::login(false, noninteractive).await;
+      let src_db = db::get_or_select_db(s_matches.value_of("src_db")).await;
+      let dst_db = db::get_or_input_arg(s_matches.value_of("dst_db"), "Target db");
+      db::promote(
+        &src_db,
+        &dst_db,
+        s_matches.value_of("mapping"),
+        s_matches.is_present("create"),
+        noninteractive,
+      )
+      .await;
+    }
+    ("template", Some(s_matches)) => match s_matches.subcommand() {
+      ("create", Some(t_matches)) => {
+        let noninteractive = t_matches.is_present("noninteractive");
+        auth::login(false, noninteractive).await;
+        let db = db::get_or_select_db(t_matches.value_of("db")).await;
+        let template_file = t_matches
+          .value_of("template_file")
+          .map(|f| f.to_string())
+          .unwrap_or(format!("{}.template.sql", db));
+        let vars = t_matches.values_of_lossy("vars").unwrap_or_default();
+        let values =
+          template::parse_assignments(t_matches.values_of_lossy("set").unwrap_or_default());
+        template::create(&db, vars, values, &template_file, noninteractive).await;
+      }
+      ("render", Some(t_matches)) => {
+        let template_file = t_matches.value_of("template_file").unwrap();
+        let dump_file = db::get_or_input_arg(t_matches.value_of("dump_file"), "Dump file");
+        let values =
+          template::parse_assignments(t_matches.values_of_lossy("set").unwrap_or_default());
+        template::render(template_file, values, &dump_file);
+      }
+      // rely on AppSettings::SubcommandRequiredElseHelp
+      _ => {}
+    },
+    ("dump", Some(s_matches)) => match s_matches.subcommand() {
+      ("diff", Some(d_matches)) => {
+        let old_dump = d_matches.value_of("old_dump").unwrap();
+        let new_dump = d_matches.value_of("new_dump").unwrap();
+        dump::diff(old_dump, new_dump);
+      }
+      ("convert", Some(d_matches)) => {
+        let dump_file = d_matches.value_of("dump_file").unwrap();
+        let format = dump::ConvertFormat::parse(d_matches.value_of("format").unwrap()).unwrap();
+        let out_dir = d_matches.value_of("out-dir").unwrap();
+        dump::convert(dump_file, format, out_dir);
+      }
+      // rely on AppSettings::SubcommandRequiredElseHelp
+      _ => {}
+    },
+    ("jobs", Some(s_matches)) => match s_matches.subcommand() {
+      ("list", Some(j_matches)) => {
+        auth::login(false, j_matches.is_present("noninteractive")).await;
+        job::list(j_matches.value_of("db")).await;
+      }
+      ("show", Some(j_matches)) => {
+        auth::login(false, j_matches.is_present("noninteractive")).await;
+        job::show(j_matches.value_of("id").unwrap()).await;
+      }
+      ("wait", Some(j_matches)) => {
+        auth::login(false, j_matches.is_present("noninteractive")).await;
+        job::wait(j_matches.value_of("id").unwrap()).await;
+      }
+      // rely on AppSettings::SubcommandRequiredElseHelp
+      _ => {}
+    },
+    ("use", Some(s_matches)) => {
+      auth::login(false, s_matches.is_present("noninteractive")).await;
+      db::use_db(s_matches.value_of("db"), s_matches.is_present("unset")).await;
+    }
+    ("up", Some(s_matches)) => {
+      let noninteractive = s_matches.is_present("noninteractive");
+      auth::login(false, noninteractive).await;
+      manifest::up(
+        s_matches.value_of("file"),
+        s_matches.is_present("create"),
+        noninteractive,
+      )
+      .await;
+    }
     // rely on AppSettings::SubcommandRequiredElseHelp
     _ => {}
   }
 }
+
+// Display filters shared by `plan`, `apply` and `sync`. Modules and tables also scope the
+// changes made by `apply` and `sync`
+fn plan_filter_args() -> Vec<Arg<'static, 'static>> {
+  vec![
+    Arg::from_usage("--action=[ACTION]...")
+      .use_delimiter(true)
+      .number_of_values(1)
+      .possible_values(&["create", "update", "replace", "delete"]),
+    Arg::from_usage("--table=[TABLE]...")
+      .use_delimiter(true)
+      .number_of_values(1),
+    Arg::from_usage("--module=[MODULE]...")
+      .use_delimiter(true)
+      .number_of_values(1),
+    Arg::from_usage("--summary"),
+  ]
+}
+
+fn plan_filter(s_matches: &ArgMatches) -> db::PlanFilter {
+  let values = |name: &str| -> Vec<String> {
+    s_matches
+      .values_of(name)
+      .map(|v| v.map(|s| s.to_string()).collect())
+      .unwrap_or_default()
+  };
+  db::PlanFilter {
+    actions: values("action")
+      .iter()
+      .filter_map(|a| db::PlanAction::parse(a))
+      .collect(),
+    tables: values("table"),
+    modules: values("module"),
+    summary: s_matches.is_present("summary"),
+  }
+}
+
+// Parses `--max-deletes` and `--max-replaces`. A plain number caps the changes in all
+// tables and `table=number` the changes in one table.
+fn apply_limits(s_matches: &ArgMatches) -> Limits {
+  let mut limits = Limits::default();
+  for (arg, max, table_max) in [
+    (
+      "max-deletes",
+      &mut limits.deletes,
+      &mut limits.table_deletes,
+    ),
+    (
+      "max-replaces",
+      &mut limits.replaces,
+      &mut limits.table_replaces,
+    ),
+  ] {
+    for value in s_matches.values_of(arg).into_iter().flatten() {
+      let parsed = match value.split_once('=') {
+        Some((table, n)) => n.trim().parse().map(|n| {
+          table_max.insert(table.trim().to_string(), n);
+        }),
+        None => value.trim().parse().map(|n| *max = Some(n)),
+      };
+      if parsed.is_err() {
+        eprintln!(
+          "{} {} {} {}",
+          dlg::err_prefix(),
+          dlg::bold(&format!("Invalid value for --{}", arg)),
+          dlg::divider(),
+          dlg::red(value),
+        );
+        exit(1);
+      }
+    }
+  }
+  limits
+}
+
+// Selection of many dbs for the commands able to run on them at once
+fn batch_args() -> Vec<Arg<'static, 'static>> {
+  vec![
+    Arg::from_usage("--all").conflicts_with("db"),
+    Arg::with_name("dbs")
+      .long("db")
+      .value_name("DB")
+      .help("Hosted db, or dbs given as several names or globs")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1)
+      .use_delimiter(true)
+      .conflicts_with("db"),
+    Arg::from_usage("--parallel=[N]").default_value("4"),
+  ]
+}
+
+// Runs the command on each of the dbs selected with `--all`, a glob or several names, and
+// exits. A single db name is left to the command itself.
+async fn maybe_run_batch(command: &str, s_matches: &ArgMatches<'_>, db_arg: &str) {
+  let patterns = s_matches.values_of_lossy(db_arg).unwrap_or_default();
+  let all = s_matches.is_present("all");
+  if !all && patterns.len() <= 1 && !patterns.iter().any(|p| batch::is_glob(p)) {
+    return;
+  }
+  let parallel = s_matches.value_of("parallel").unwrap();
+  let parallel = match parallel.parse::<usize>() {
+    Ok(n) if n > 0 => n,
+    _ => {
+      eprintln!(
+        "{} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Invalid value for --parallel"),
+        dlg::divider(),
+        dlg::red(parallel),
+      );
+      exit(1);
+    }
+  };
+  let noninteractive = s_matches.is_present("noninteractive");
+  auth::login(false, noninteractive).await;
+  batch::run(command, s_matches, &patterns, all, parallel, noninteractive).await;
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::process::exit;
+
+use serde::Deserialize;
+
+use crate::api::{db, module};
+use crate::dialoguer as dlg;
+
+// Project file used when none is given with `--file`
+const DEFAULT_MANIFEST_FILE: &str = "iasql.toml";
+
+// A project file declares the hosted db of a project and the modules it must have
+// installed, each with an exact version, a version range or `latest`. The region is only
+// used when the db has to be provisioned.
+//
+// db = "prod"
+// region = "us-east-1"
+//
+// [modules]
+// aws_ec2 = "0.0.1"
+// aws_rds = "^0.1"
+// aws_ecr = "latest"
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+  db: String,
+  region: Option<String>,
+  #[serde(default)]
+  modules: BTreeMap<String, String>,
+}
+
+impl Manifest {
+  fn from_file(manifest_file: &str) -> Manifest {
+    match read_to_string(manifest_file)
+      .map_err(|e| e.to_string())
+      .and_then(|m| toml::from_str(&m).map_err(|e| e.to_string()))
+    {
+      Ok(manifest) => manifest,
+      Err(e) => dlg::exit_with_error(
+        &format!("Failed to read project file {}", manifest_file),
+        &e,
+      ),
+    }
+  }
+
+  fn modules(&self) -> Vec<String> {
+    self
+      .modules
+      .iter()
+      .map(|(name, version)| format!("{}@{}", name, version))
+      .collect()
+  }
+}
+
+fn print_changes(header: &str, mods: &[String]) {
+  if mods.is_empty() {
+    return;
+  }
+  println!("{}", dlg::bold(header));
+  for m in mods.iter() {
+    println!("  {}", m);
+  }
+}
+
+// Provisions the db of the project file if needed and allowed, then installs and
+// uninstalls modules until the db has exactly the modules declared and their dependencies
+pub async fn up(manifest_file: Option<&str>, create: bool, noninteractive: bool) {
+  let manifest_file = manifest_file.unwrap_or(DEFAULT_MANIFEST_FILE);
+  let manifest = Manifest::from_file(manifest_file);
+  let dbs = db::get_dbs(false).await;
+  let db = if dbs.contains(&manifest.db) {
+    println!(
+      "{} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold("IaSQL db"),
+      dlg::divider(),
+      dlg::green(&manifest.db),
+    );
+    manifest.db.clone()
+  } else {
+    let prompt = format!("Hosted db {} does not exist. Create it", manifest.db);
+    if !create && (noninteractive || !dlg::confirm_with_default(&prompt, false)) {
+      dlg::exit_with_cause(
+        "Nonexistent hosted db",
+        &manifest.db,
+        "use --create to provision it",
+      );
+    }
+    db::new(
+      Some(&manifest.db),
+      manifest.region.as_deref(),
+      noninteractive,
+    )
+    .await
+  };
+  let (to_install, to_remove) = module::mods_to_reconcile(&db, manifest.modules()).await;
+  if to_install.is_empty() && to_remove.is_empty() {
+    println!(
+      "{} {} {} {}",
+      dlg::success_prefix(),
+      dlg::bold("Modules are up to date in db"),
+      dlg::divider(),
+      dlg::green(&db),
+    );
+    return;
+  }
+  print_changes("Modules to install:", &to_install);
+  print_changes("Modules to uninstall:", &to_remove);
+  if !noninteractive && !dlg::confirm_with_default("Confirm module changes", true) {
+    println!(
+      "{} {} {} {}",
+      dlg::warn_prefix(),
+      dlg::bold("No modules were changed in db"),
+      dlg::divider(),
+      dlg::yellow(&db),
+    );
+    exit(0);
+  }
+  // modules are removed first, nothing left installed depends on them
+  if !to_remove.is_empty() {
+    module::uninstall(&db, to_remove, true).await;
+  }
+  if !to_install.is_empty() {
+    module::install(&db, to_install, true).await;
+  }
+}
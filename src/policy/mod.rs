@@ -0,0 +1,376 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::api::db::{PlanAction, PlanMeta, PlanResponse};
+use crate::dialoguer as dlg;
+
+// Policy file used when none is given with `--policy`
+const DEFAULT_POLICY_FILE: &str = "iasql-policy.toml";
+
+// A policy file is a list of rules. Each rule selects changes by action and table, and
+// the selected changes break the rule when:
+//  - `deny` is set
+//  - there are more than `max` of them
+//  - the value of `column` does not contain `contains`
+//
+// [[rule]]
+// name = "never delete rds instances"
+// action = "delete"
+// table = "rds"
+// deny = true
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+  name: String,
+  action: Option<String>,
+  table: Option<String>,
+  #[serde(default)]
+  deny: bool,
+  max: Option<usize>,
+  column: Option<String>,
+  contains: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Policy {
+  #[serde(default)]
+  rule: Vec<Rule>,
+}
+
+struct Violation {
+  rule: String,
+  detail: String,
+}
+
+// Policy to enforce on a plan and whether breaking it stops the command
+pub struct PolicyCheck {
+  policy_file: String,
+  policy: Policy,
+  allow_violations: bool,
+}
+
+impl Rule {
+  fn validate(&self) -> Result<(), String> {
+    if let Some(action) = &self.action {
+      if PlanAction::parse(action).is_none() {
+        return Err(format!("unknown action {}", action));
+      }
+    }
+    let checks = [self.deny, self.max.is_some(), self.contains.is_some()];
+    if checks.iter().filter(|c| **c).count() != 1 {
+      return Err("expected exactly one of deny, max or contains".to_string());
+    }
+    if self.contains.is_some() != self.column.is_some() {
+      return Err("column and contains must be set together".to_string());
+    }
+    Ok(())
+  }
+
+  // Tables of the plan, and their changes, selected by the rule
+  fn selected<'a>(&self, plan: &'a PlanResponse) -> Vec<(PlanAction, &'a String, &'a PlanMeta)> {
+    let mut selected = vec![];
+    for action in PlanAction::all().iter() {
+      if let Some(a) = &self.action {
+        if a != action.verb() {
+          continue;
+        }
+      }
+      for (table, meta) in plan.segment(*action).iter() {
+        match &self.table {
+          Some(t) if t != table => {}
+          _ => selected.push((*action, table, meta)),
+        }
+      }
+    }
+    selected
+  }
+
+  fn evaluate(&self, plan: &PlanResponse) -> Vec<Violation> {
+    let selected = self.selected(plan);
+    let violation = |detail: String| Violation {
+      rule: self.name.clone(),
+      detail,
+    };
+    if let Some(max) = self.max {
+      let count: usize = selected
+        .iter()
+        .map(|(_, _, meta)| meta.records().len())
+        .sum();
+      if count > max {
+        return vec![violation(format!(
+          "{} changes, at most {} allowed",
+          count, max
+        ))];
+      }
+      return vec![];
+    }
+    let mut violations = vec![];
+    for (action, table, meta) in selected.iter() {
+      let key_index = meta.key_index();
+      let column_index = self
+        .column
+        .as_ref()
+        .map(|c| meta.columns().iter().position(|mc| mc == c));
+      for record in meta.records().iter() {
+        let key = format!(
+          "{} {}={} to {}",
+          table,
+          meta.columns()[key_index],
+          record.get(key_index).cloned().unwrap_or_default(),
+          action.verb()
+        );
+        match (&column_index, &self.contains) {
+          (Some(Some(i)), Some(contains)) => {
+            let value = record.get(*i).map(|v| v.as_str()).unwrap_or("");
+            if !value.contains(contains.as_str()) {
+              violations.push(violation(format!(
+                "{}: {} does not contain {}",
+                key,
+                self.column.as_deref().unwrap_or(""),
+                contains
+              )));
+            }
+          }
+          (Some(None), _) => violations.push(violation(format!(
+            "{}: no column {}",
+            key,
+            self.column.as_deref().unwrap_or("")
+          ))),
+          _ => violations.push(violation(key)),
+        }
+      }
+    }
+    violations
+  }
+}
+
+impl Policy {
+  fn from_file(policy_file: &str) -> Policy {
+    let policy: Policy = match read_to_string(policy_file)
+      .map_err(|e| e.to_string())
+      .and_then(|p| toml::from_str(&p).map_err(|e| e.to_string()))
+    {
+      Ok(policy) => policy,
+      Err(e) => dlg::exit_with_error(&format!("Failed to read policy file {}", policy_file), &e),
+    };
+    for rule in policy.rule.iter() {
+      if let Err(e) = rule.validate() {
+        dlg::exit_with_error(&format!("Invalid policy rule {}", rule.name), &e);
+      }
+    }
+    policy
+  }
+
+  fn evaluate(&self, plan: &PlanResponse) -> Vec<Violation> {
+    self.rule.iter().flat_map(|r| r.evaluate(plan)).collect()
+  }
+}
+
+impl PolicyCheck {
+  // Loads the given policy file, or the default one if present in the current directory
+  pub fn load(policy_file: Option<&str>, allow_violations: bool) -> Option<PolicyCheck> {
+    let policy_file = match policy_file {
+      Some(f) => f,
+      None if Path::new(DEFAULT_POLICY_FILE).exists() => DEFAULT_POLICY_FILE,
+      None => return None,
+    };
+    Some(PolicyCheck {
+      policy_file: policy_file.to_string(),
+      policy: Policy::from_file(policy_file),
+      allow_violations,
+    })
+  }
+
+  pub fn load_required(policy_file: Option<&str>, allow_violations: bool) -> PolicyCheck {
+    match PolicyCheck::load(policy_file, allow_violations) {
+      Some(policy) => policy,
+      None => dlg::exit_with_error("No policy file found", DEFAULT_POLICY_FILE),
+    }
+  }
+
+  // Lists the rules the plan breaks and exits unless violations are allowed. Everything
+  // goes to stderr so plans printed as json or reports can still be piped.
+  pub fn enforce(&self, plan: &PlanResponse) {
+    let violations = self.policy.evaluate(plan);
+    if violations.is_empty() {
+      eprintln!(
+        "{} {} {} {}",
+        dlg::success_prefix(),
+        dlg::bold("Plan complies with policy"),
+        dlg::divider(),
+        dlg::green(&self.policy_file),
+      );
+      return;
+    }
+    for v in violations.iter() {
+      eprintln!(
+        "{} {} {} {} {} {}",
+        dlg::err_prefix(),
+        dlg::bold("Policy violation"),
+        dlg::divider(),
+        dlg::red(&v.rule),
+        dlg::divider(),
+        v.detail,
+      );
+    }
+    let violation_text = if violations.len() == 1 {
+      "violation"
+    } else {
+      "violations"
+    };
+    if self.allow_violations {
+      eprintln!(
+        "{} {}",
+        dlg::warn_prefix(),
+        dlg::bold(&format!(
+          "Continuing despite {} policy {}",
+          violations.len(),
+          violation_text
+        )),
+      );
+      return;
+    }
+    dlg::exit_with_error(
+      &format!(
+        "Plan has {} policy {}. Use --allow-policy-violations to override",
+        violations.len(),
+        violation_text
+      ),
+      &self.policy_file,
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rules(policy: &str) -> Vec<Rule> {
+    toml::from_str::<Policy>(policy).unwrap().rule
+  }
+
+  fn plan() -> PlanResponse {
+    serde_json::from_str(
+      r#"{
+        "iasqlPlanVersion": 3,
+        "toCreate": {
+          "instance": {
+            "columns": ["id", "name", "tags"],
+            "records": [["1", "web", "team=core"], ["2", "worker", ""]]
+          }
+        },
+        "toUpdate": {},
+        "toReplace": {},
+        "toDelete": {
+          "rds": { "columns": ["id", "name"], "records": [["3", "main"]] }
+        }
+      }"#,
+    )
+    .unwrap()
+  }
+
+  fn details(rule: &Rule) -> Vec<String> {
+    rule
+      .evaluate(&plan())
+      .into_iter()
+      .map(|v| v.detail)
+      .collect()
+  }
+
+  #[test]
+  fn denies_the_selected_changes() {
+    let rules = rules(
+      r#"
+      [[rule]]
+      name = "keep rds"
+      action = "delete"
+      table = "rds"
+      deny = true
+
+      [[rule]]
+      name = "no instance deletes"
+      action = "delete"
+      table = "instance"
+      deny = true
+      "#,
+    );
+    assert!(rules.iter().all(|r| r.validate().is_ok()));
+    assert_eq!(details(&rules[0]), vec!["rds id=3 to delete"]);
+    assert!(details(&rules[1]).is_empty());
+  }
+
+  #[test]
+  fn caps_the_number_of_changes() {
+    let rules = rules(
+      r#"
+      [[rule]]
+      name = "few changes"
+      max = 2
+
+      [[rule]]
+      name = "few creates"
+      action = "create"
+      max = 2
+      "#,
+    );
+    assert_eq!(details(&rules[0]), vec!["3 changes, at most 2 allowed"]);
+    assert!(details(&rules[1]).is_empty());
+  }
+
+  #[test]
+  fn requires_column_values() {
+    let rules = rules(
+      r#"
+      [[rule]]
+      name = "tagged instances"
+      table = "instance"
+      column = "tags"
+      contains = "team="
+
+      [[rule]]
+      name = "tagged rds"
+      table = "rds"
+      column = "tags"
+      contains = "team="
+      "#,
+    );
+    assert!(rules[0].validate().is_ok());
+    assert_eq!(
+      details(&rules[0]),
+      vec!["instance id=2 to create: tags does not contain team="]
+    );
+    assert_eq!(
+      details(&rules[1]),
+      vec!["rds id=3 to delete: no column tags"]
+    );
+  }
+
+  #[test]
+  fn rejects_rules_without_a_single_check() {
+    let rules = rules(
+      r#"
+      [[rule]]
+      name = "nothing"
+      table = "rds"
+
+      [[rule]]
+      name = "both"
+      deny = true
+      max = 1
+
+      [[rule]]
+      name = "contains without column"
+      contains = "team="
+
+      [[rule]]
+      name = "unknown action"
+      action = "drop"
+      deny = true
+      "#,
+    );
+    assert!(rules.iter().all(|r| r.validate().is_err()));
+  }
+}
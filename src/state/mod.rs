@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dialoguer as dlg;
+use crate::http::get_env;
+
+const STATE_FILE: &str = ".iasql/state.toml";
+
+// Settings written by the CLI itself, kept apart from the user's config file. The db set
+// with `iasql use` is kept per environment of the engine:
+//
+// [current_db]
+// production = "prod"
+// staging = "staging"
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct State {
+  current_db: BTreeMap<String, String>,
+}
+
+fn state_file() -> String {
+  let home = std::env::var("HOME").unwrap();
+  format!("{}/{}", home, STATE_FILE)
+}
+
+impl State {
+  // An absent or unreadable state file is the same as an empty one
+  pub fn load() -> State {
+    read_to_string(state_file())
+      .ok()
+      .and_then(|content| toml::from_str(&content).ok())
+      .unwrap_or_default()
+  }
+
+  pub fn save(&self) {
+    let file_name = state_file();
+    let result = toml::to_string(self)
+      .map_err(|e| e.to_string())
+      .and_then(|content| {
+        if let Some(dir) = Path::new(&file_name).parent() {
+          create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        write(&file_name, content).map_err(|e| e.to_string())
+      });
+    if let Err(e) = result {
+      dlg::exit_with_cause("Failed to write state file", &file_name, e);
+    }
+  }
+
+  // Db set with `iasql use` for the current environment
+  pub fn current_db(&self) -> Option<String> {
+    self.current_db.get(&get_env()).cloned()
+  }
+
+  pub fn set_current_db(&mut self, db: Option<&str>) {
+    match db {
+      Some(db) => {
+        self.current_db.insert(get_env(), db.to_string());
+      }
+      None => {
+        self.current_db.remove(&get_env());
+      }
+    }
+  }
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+
+use crate::api::db::export_dump;
+use crate::dialoguer as dlg;
+use crate::dump::map_values;
+
+// Templates are dumps generated by `export` where the values of chosen variables have been
+// replaced by `{{name}}` placeholders. Only whole values are replaced, so a placeholder is
+// always a complete SQL literal or `COPY` field. Values of the dump that already start with
+// `{{` are escaped with a leading backslash. The first line of a template lists the
+// variables it expects.
+const VARS_HEADER: &str = "-- iasql-template-vars:";
+
+fn placeholder(var: &str) -> String {
+  format!("{{{{{}}}}}", var)
+}
+
+// Variable of a value that is a placeholder
+fn placeholder_var(value: &str) -> Option<&str> {
+  value
+    .strip_prefix("{{")
+    .and_then(|v| v.strip_suffix("}}"))
+    .filter(|v| valid_var_name(v))
+}
+
+fn escape(value: &str) -> String {
+  if value.trim_start_matches('\\').starts_with("{{") {
+    format!("\\{}", value)
+  } else {
+    value.to_string()
+  }
+}
+
+fn unescape(value: &str) -> &str {
+  match value.strip_prefix('\\') {
+    Some(rest) if rest.trim_start_matches('\\').starts_with("{{") => rest,
+    _ => value,
+  }
+}
+
+fn valid_var_name(var: &str) -> bool {
+  let mut chars = var.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+    _ => return false,
+  }
+  chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Parses `--set name=value` assignments
+pub fn parse_assignments(assignments: Vec<String>) -> BTreeMap<String, String> {
+  let mut values = BTreeMap::new();
+  for assignment in assignments.iter() {
+    match assignment.split_once('=') {
+      Some((var, value)) if valid_var_name(var.trim()) => {
+        values.insert(var.trim().to_string(), value.to_string());
+      }
+      _ => dlg::exit_with_error("Invalid variable assignment", assignment),
+    }
+  }
+  values
+}
+
+// Exports a hosted db and replaces the values of the variables with their placeholders
+pub async fn create(
+  db: &str,
+  vars: Vec<String>,
+  values: BTreeMap<String, String>,
+  out: &str,
+  noninteractive: bool,
+) {
+  if vars.is_empty() {
+    dlg::exit_with_error("No template variables provided for db", db);
+  }
+  if let Some(var) = vars.iter().find(|v| !valid_var_name(v)) {
+    dlg::exit_with_error("Invalid template variable name", var);
+  }
+  if let Some(var) = values.keys().find(|v| !vars.contains(v)) {
+    dlg::exit_with_error("Unused template variable", var);
+  }
+  let mut replacements: BTreeMap<String, String> = BTreeMap::new();
+  for var in vars.iter() {
+    let value = match values.get(var) {
+      Some(value) => value.clone(),
+      None if noninteractive => {
+        dlg::exit_with_cause("No value set for template variable", var, "use --set")
+      }
+      None => dlg::input(&format!("Value to replace with {}", placeholder(var))),
+    };
+    if value.is_empty() {
+      dlg::exit_with_error("Empty value for template variable", var);
+    }
+    if let Some(other) = replacements.insert(value, var.clone()) {
+      dlg::exit_with_cause("Template variables have the same value", var, other);
+    }
+  }
+  let dump = export_dump(db, false).await;
+  let mut used = BTreeSet::new();
+  let template = map_values(&dump, |value| match replacements.get(value) {
+    Some(var) => {
+      used.insert(var.clone());
+      placeholder(var)
+    }
+    None => escape(value),
+  });
+  if let Some(var) = vars.iter().find(|v| !used.contains(*v)) {
+    dlg::exit_with_error("Value of template variable not found in db", var);
+  }
+  let template = format!("{} {}\n{}", VARS_HEADER, vars.join(","), template);
+  if let Err(e) = write(out, template) {
+    dlg::exit_with_error("Failed to write template file", &e.to_string());
+  }
+  println!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold("Template created"),
+    dlg::divider(),
+    dlg::green(out),
+  );
+}
+
+// Replaces every placeholder in a template with the value set for its variable
+pub fn render(template_file: &str, values: BTreeMap<String, String>, out: &str) {
+  let template = match read_to_string(template_file) {
+    Ok(t) => t,
+    Err(e) => dlg::exit_with_error("Failed to read template file", &e.to_string()),
+  };
+  let (header, body) = template.split_once('\n').unwrap_or((&template, ""));
+  let declared: BTreeSet<String> = match header.strip_prefix(VARS_HEADER) {
+    Some(vars) => vars.split(',').map(|v| v.trim().to_string()).collect(),
+    None => dlg::exit_with_error("Not a template file", template_file),
+  };
+  if let Some(var) = declared.iter().find(|v| !values.contains_key(*v)) {
+    dlg::exit_with_error("Undefined template variable", var);
+  }
+  if let Some(var) = values.keys().find(|v| !declared.contains(*v)) {
+    dlg::exit_with_error("Unused template variable", var);
+  }
+  let mut undeclared = BTreeSet::new();
+  let dump = map_values(body, |value| match placeholder_var(value) {
+    Some(var) => match values.get(var) {
+      Some(replacement) => replacement.clone(),
+      None => {
+        undeclared.insert(var.to_string());
+        value.to_string()
+      }
+    },
+    None => unescape(value).to_string(),
+  });
+  if let Some(var) = undeclared.iter().next() {
+    dlg::exit_with_error("Undefined template variable", var);
+  }
+  if let Err(e) = write(out, dump) {
+    dlg::exit_with_error("Failed to write dump file", &e.to_string());
+  }
+  println!(
+    "{} {} {} {}",
+    dlg::success_prefix(),
+    dlg::bold("Dump rendered"),
+    dlg::divider(),
+    dlg::green(out),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn escapes_values_looking_like_placeholders() {
+    for value in ["{{region}}", "\\{{x}}", "{{", "plain", "\\n"].iter() {
+      assert_eq!(unescape(&escape(value)), *value);
+      assert_eq!(placeholder_var(&escape(value)), None);
+    }
+    assert_eq!(placeholder_var("{{env_name}}"), Some("env_name"));
+    assert_eq!(placeholder_var("{{not a var}}"), None);
+  }
+
+  #[test]
+  fn replaces_whole_values_only() {
+    let dump = "INSERT INTO t (id, env, note) VALUES (1, 'dev', 'devops {{dev}}');\n";
+    let replacements: BTreeMap<String, String> = vec![("dev".to_string(), "env".to_string())]
+      .into_iter()
+      .collect();
+    let template = map_values(dump, |value| match replacements.get(value) {
+      Some(var) => placeholder(var),
+      None => escape(value),
+    });
+    assert_eq!(
+      template,
+      "INSERT INTO t (id, env, note) VALUES (1, '{{env}}', 'devops {{dev}}');\n"
+    );
+  }
+}